@@ -0,0 +1,32 @@
+// Parses arbitrary text as a program and runs it every way the crate can. Run with
+// 'cargo fuzz run eval' from 'p1'.
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate your;
+
+use your::{eval_traced, eval_with_words, optimize, parse_program, to_infix, CompiledProgram, Dictionary, Env, EvalOptions, OverflowPolicy};
+
+fuzz_target!(|data: &[u8]| {
+	let text = match std::str::from_utf8(data) {
+		Ok(text) => text,
+		Err(_) => return,
+	};
+	let mut words = Dictionary::new();
+	let tokens = match parse_program(text, &mut words) {
+		Ok(tokens) => tokens,
+		Err(_) => return,
+	};
+	let mut env = Env::new();
+	env.insert("x".to_string(), 7);
+	for &overflow in &[OverflowPolicy::Checked, OverflowPolicy::Wrapping, OverflowPolicy::Saturating, OverflowPolicy::Arbitrary] {
+		let options = EvalOptions { overflow };
+		let _ = eval_with_words(&tokens, &mut env.clone(), &words, &options);
+		let _ = eval_with_words(&optimize(&tokens), &mut env.clone(), &words, &options);
+		if let Ok(program) = CompiledProgram::with_words(&tokens, &words) {
+			let _ = program.eval(&mut env.clone(), &options);
+		}
+	}
+	let _ = eval_traced(&tokens, env, &EvalOptions::default());
+	let _ = to_infix(&tokens);
+});
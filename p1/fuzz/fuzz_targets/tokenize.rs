@@ -0,0 +1,18 @@
+// Feeds arbitrary text to the lexers. Run with 'cargo fuzz run tokenize' from 'p1'.
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate your;
+
+use your::{parse_postfix, parse_program, Dictionary};
+
+fuzz_target!(|data: &[u8]| {
+	if let Ok(text) = std::str::from_utf8(data) {
+		// whatever parses prints back to text that parses the same
+		if let Ok(tokens) = parse_postfix(text) {
+			let printed = tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" ");
+			assert_eq!(Ok(tokens), parse_postfix(&printed));
+		}
+		let _ = parse_program(text, &mut Dictionary::new());
+	}
+});
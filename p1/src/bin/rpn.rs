@@ -0,0 +1,217 @@
+// rpn: an interactive calculator on top of the postfix evaluator
+//
+// Usage: rpn          reads lines from stdin, keeping the stack between lines
+//        rpn FILE     runs FILE as a script and exits with status 1 on the first error
+extern crate your;
+
+use std::{env, fs, process};
+use std::io::{self, BufRead, Write};
+
+use your::{exec_with_words, parse_program, Dictionary, Env, ErrorKind, EvalError, EvalOptions};
+
+const HELP: &str = "\
+Enter postfix expressions, e.g. '1 2 + 3 *'. The stack is kept between lines.
+  numbers            push a value
+  + - * / % ^        binary operators
+  neg abs min max    other operators
+  = <> < <= > >=     comparisons, giving 1 or 0
+  and or not         logic, where any non-zero value is true
+  if ... else ... then
+                     run one branch or the other, e.g. 'x 0 < if x neg then'
+  dup drop swap over rot pick(n) clear
+                     stack words
+  name  ->name       push a variable, or pop the top value into it
+  : name ... ;       define a word, e.g. ': square dup * ;'
+Commands:
+  :help              show this text
+  :clear             empty the stack
+  :undo              undo the last line
+  :history           list the lines entered so far
+  :quit              exit";
+
+// The state kept between lines.
+struct Session {
+	stack: Vec<isize>,
+	env: Env,
+	words: Dictionary,
+	// the stack and variables before each line, for ':undo'
+	undo: Vec<(Vec<isize>, Env)>,
+	// the lines that parsed, and each ':clear'
+	history: Vec<String>,
+}
+
+impl Session {
+	fn new() -> Session {
+		Session { stack: Vec::new(), env: Env::new(), words: Dictionary::new(), undo: Vec::new(), history: Vec::new() }
+	}
+
+	// Runs one line of input. Returns the text to show, or an error message; on error
+	// the stack and variables are left as they were before the line. Words defined on
+	// a line stay defined, even after ':undo'.
+	fn line(&mut self, line: &str) -> Result<String, String> {
+		let line = line.trim();
+		match line {
+			"" => return Ok(self.show()),
+			":help" => return Ok(HELP.to_string()),
+			":clear" => {
+				self.history.push(line.to_string());
+				self.save();
+				self.stack.clear();
+				return Ok(self.show());
+			}
+			":undo" => {
+				let (stack, env) = self.undo.pop().ok_or("nothing to undo")?;
+				self.stack = stack;
+				self.env = env;
+				return Ok(self.show());
+			}
+			":history" => {
+				let lines: Vec<String> = self.history.iter().enumerate()
+					.map(|(i, l)| format!("{:>4}  {}", i + 1, l))
+					.collect();
+				return Ok(lines.join("\n"));
+			}
+			// a lone ':' starts a word definition
+			_ if line.starts_with(':') && !line.starts_with(": ") && line != ":" => {
+				return Err(format!("unknown command '{}', try :help", line));
+			}
+			_ => {}
+		}
+		let tokens = parse_program(line, &mut self.words).map_err(|e| e.to_string())?;
+		self.history.push(line.to_string());
+		// a name that is neither a word nor a variable is most likely a misspelled word
+		if let Some(&(index, ref name)) = self.words.unknown_names(&tokens, &self.env).first() {
+			return Err(format!("{} ('{}')", EvalError { index, kind: ErrorKind::UndefinedWord }, name));
+		}
+		self.save();
+		if let Err(e) = exec_with_words(&tokens, &mut self.stack, &mut self.env, &self.words, &EvalOptions::default()) {
+			// put everything back the way it was before the line
+			let (stack, env) = self.undo.pop().unwrap();
+			self.stack = stack;
+			self.env = env;
+			return Err(format!("{} ('{}')", e, tokens[e.index]));
+		}
+		Ok(self.show())
+	}
+
+	fn save(&mut self) {
+		self.undo.push((self.stack.clone(), self.env.clone()));
+	}
+
+	// Formats the stack bottom first, e.g. "[1 2 3]".
+	fn show(&self) -> String {
+		let values: Vec<String> = self.stack.iter().map(|x| x.to_string()).collect();
+		format!("[{}]", values.join(" "))
+	}
+}
+
+fn main() {
+	let args: Vec<String> = env::args().collect();
+	match args.len() {
+		1 => interactive(),
+		2 => script(&args[1]),
+		_ => {
+			eprintln!("usage: {} [FILE]", args[0]);
+			process::exit(2);
+		}
+	}
+}
+
+// Reads lines from stdin until end of input or ':quit', printing the stack after each.
+// A line that is not UTF-8 is reported and skipped; any other read error exits with
+// status 1.
+fn interactive() {
+	let mut session = Session::new();
+	let stdin = io::stdin();
+	let mut out = io::stdout();
+	loop {
+		print!("> ");
+		out.flush().unwrap();
+		let mut line = String::new();
+		match stdin.lock().read_line(&mut line) {
+			Ok(0) => break,
+			Ok(_) => {}
+			// the bad line has been read, so the session can go on
+			Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+				eprintln!("error: {}", e);
+				continue;
+			}
+			Err(e) => {
+				eprintln!("error: {}", e);
+				process::exit(1);
+			}
+		}
+		if line.trim() == ":quit" {
+			break;
+		}
+		match session.line(&line) {
+			Ok(text) => println!("{}", text),
+			Err(msg) => eprintln!("error: {}", msg),
+		}
+	}
+}
+
+// Runs every line of 'path', then prints the final stack. Stops with status 1 at the
+// first error.
+fn script(path: &str) {
+	let text = match fs::read_to_string(path) {
+		Ok(text) => text,
+		Err(e) => {
+			eprintln!("error: {}: {}", path, e);
+			process::exit(1);
+		}
+	};
+	let mut session = Session::new();
+	for (n, line) in text.lines().enumerate() {
+		if line.trim() == ":quit" {
+			break;
+		}
+		if let Err(msg) = session.line(line) {
+			eprintln!("error: {}:{}: {}", path, n + 1, msg);
+			process::exit(1);
+		}
+	}
+	println!("{}", session.show());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Session;
+
+	#[test]
+	fn keeps_stack_between_lines() {
+		let mut s = Session::new();
+		assert_eq!(Ok("[1 2]".to_string()), s.line("1 2"));
+		assert_eq!(Ok("[3]".to_string()), s.line("+"));
+		assert_eq!(Ok("[3 4]".to_string()), s.line("4 ->x x"));
+		assert_eq!(Ok("[]".to_string()), s.line(":clear"));
+		assert_eq!(Ok("[3 4]".to_string()), s.line(":undo"));
+		assert_eq!(Ok("[3]".to_string()), s.line(":undo"));
+		assert_eq!(Ok("   1  1 2\n   2  +\n   3  4 ->x x\n   4  :clear".to_string()), s.line(":history"));
+	}
+
+	#[test]
+	fn errors_leave_stack_alone() {
+		let mut s = Session::new();
+		s.line("1 2").unwrap();
+		assert_eq!(Err("stack underflow at token 1 ('+')".to_string()), s.line("+ + +"));
+		assert_eq!(Ok("[1 2]".to_string()), s.line(""));
+		assert_eq!(Err("unknown token '#' at column 3".to_string()), s.line("1 #"));
+		assert!(s.line(":nope").is_err());
+		// only lines that parse are kept
+		assert_eq!(Ok("   1  1 2\n   2  + + +".to_string()), s.line(":history"));
+		assert_eq!(Err("nothing to undo".to_string()), Session::new().line(":undo"));
+	}
+
+	#[test]
+	fn defines_words() {
+		let mut s = Session::new();
+		assert_eq!(Ok("[]".to_string()), s.line(": square dup * ;"));
+		assert_eq!(Ok("[9]".to_string()), s.line("3 square"));
+		// variables stored on earlier lines can still be read
+		assert_eq!(Ok("[9]".to_string()), s.line("2 ->y"));
+		assert_eq!(Ok("[9 2]".to_string()), s.line("y"));
+		assert_eq!(Err("undefined word at token 0 ('sqare')".to_string()), s.line("sqare"));
+		assert_eq!(Err("word 'square' is already defined at column 3".to_string()), s.line(": square 2 * ;"));
+	}
+}
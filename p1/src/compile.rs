@@ -0,0 +1,596 @@
+use std::mem;
+
+use {Dictionary, Env, ErrorKind, EvalError, EvalOptions, Number, Operator, StackOp, Token};
+
+// One instruction of a compiled program. Variables are resolved to slots, and each 'If'
+// and 'Else' holds the position of the instruction to jump to.
+#[derive(Clone, Debug, PartialEq)]
+enum Instr<T> {
+	Push(T),
+	Op(Operator),
+	Stack(StackOp),
+	Load(usize),
+	Store(usize),
+	// pops a value and jumps past the 'Else' or to the 'Then' if it is zero
+	If(usize),
+	// ends the 'if' branch by jumping to the 'Then'
+	Else(usize),
+	Then,
+}
+
+/// A postfix program checked once up front so it can be evaluated many times quickly.
+///
+/// Compiling works out the stack depth before and after every token, so underflow and
+/// leftover operands are rejected here rather than during evaluation, and the stack is
+/// allocated at its exact maximum size. Both branches of a conditional must leave the
+/// same depth, or compiling fails with 'ErrorKind::UnbalancedBranches'; an 'if' without
+/// an 'else' must leave the depth as it was. User words are inlined. Evaluation can
+/// still fail on the values themselves, e.g. with 'ErrorKind::Overflow' or
+/// 'ErrorKind::DivideByZero', and 'OverflowPolicy::Arbitrary' is applied to each
+/// operation as with 'exec'.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledProgram<T = isize> {
+	code: Vec<Instr<T>>,
+	// the index of the top-level token each instruction came from
+	origin: Vec<usize>,
+	// variable names, indexed by slot
+	names: Vec<String>,
+	// for each slot, the token index of the first read that is not preceded by a store
+	first_read: Vec<Option<usize>>,
+	max_stack: usize,
+}
+
+// What the compiler tracks between tokens.
+struct State {
+	// the stack depth after the tokens so far
+	depth: usize,
+	// for each slot, whether a store has been compiled
+	stored: Vec<bool>,
+	// the conditionals not yet closed by a 'then', innermost last
+	branches: Vec<Branch>,
+	// how many of 'branches' were opened outside the word body being compiled, which an
+	// 'else' or 'then' inside it cannot close
+	outer: usize,
+}
+
+// An open conditional.
+struct Branch {
+	// the position of the 'If' instruction, and of the 'Else' once there is one
+	at: usize,
+	else_at: Option<usize>,
+	// the depth after the condition is popped
+	depth: usize,
+	// the depth at the end of the 'if' branch, once there is an 'else'
+	then_depth: usize,
+}
+
+impl<T: Number> CompiledProgram<T> {
+	/// Compiles 'tokens'. 'Call' tokens fail with 'ErrorKind::UndefinedWord'.
+	pub fn new(tokens: &[Token<T>]) -> Result<CompiledProgram<T>, EvalError> {
+		CompiledProgram::with_words(tokens, &Dictionary::new())
+	}
+
+	/// Compiles 'tokens', inlining 'Call' tokens from 'words'.
+	///
+	/// Words that call themselves, or nest deeper than 'words.max_depth()', fail with
+	/// 'ErrorKind::RecursionLimit'. A conditional has to end in the word body or program
+	/// it starts in.
+	pub fn with_words(tokens: &[Token<T>], words: &Dictionary<T>) -> Result<CompiledProgram<T>, EvalError> {
+		let mut program = CompiledProgram {
+			code: Vec::new(),
+			origin: Vec::new(),
+			names: Vec::new(),
+			first_read: Vec::new(),
+			max_stack: 0,
+		};
+		let mut state = State { depth: 0, stored: Vec::new(), branches: Vec::new(), outer: 0 };
+		for (index, token) in tokens.iter().enumerate() {
+			program.emit(token, index, words, 0, &mut state)
+				.map_err(|kind| EvalError { index, kind })?;
+		}
+		if let Some(branch) = state.branches.first() {
+			return Err(EvalError { index: program.origin[branch.at], kind: ErrorKind::UnmatchedConditional });
+		}
+		// exactly one value has to be left, as with 'eval'
+		match state.depth {
+			0 => Err(EvalError { index: tokens.len(), kind: ErrorKind::Underflow }),
+			1 => Ok(program),
+			_ => Err(EvalError { index: tokens.len(), kind: ErrorKind::TrailingOperands }),
+		}
+	}
+
+	// Appends the instructions for 'token', which is or is inside the top-level token
+	// 'index'. 'calls' is the number of words the token is nested in.
+	fn emit(&mut self, token: &Token<T>, index: usize, words: &Dictionary<T>, calls: usize, state: &mut State) -> Result<(), ErrorKind> {
+		let depth = state.depth;
+		// the values the token needs, and the depth it leaves behind
+		let (needs, after) = match *token {
+			Token::Operand(_) | Token::Var(_) => (0, depth + 1),
+			Token::Operator(ref op) => (op.arity(), (depth + 1).saturating_sub(op.arity())),
+			Token::Store(_) | Token::If => (1, depth.saturating_sub(1)),
+			Token::Stack(ref op) => (op.depth(), match *op {
+				StackOp::Dup | StackOp::Over | StackOp::Pick(_) => depth + 1,
+				StackOp::Drop => depth.saturating_sub(1),
+				StackOp::Swap | StackOp::Rot => depth,
+				StackOp::Clear => 0,
+			}),
+			Token::Else | Token::Then => return self.close(token, index, state),
+			Token::Call(ref name) => {
+				let body = words.get(name).ok_or(ErrorKind::UndefinedWord)?;
+				if calls >= words.max_depth() {
+					return Err(ErrorKind::RecursionLimit);
+				}
+				let outer = mem::replace(&mut state.outer, state.branches.len());
+				for t in body {
+					self.emit(t, index, words, calls + 1, state)?;
+				}
+				if state.branches.len() != state.outer {
+					return Err(ErrorKind::UnmatchedConditional);
+				}
+				state.outer = outer;
+				return Ok(());
+			}
+		};
+		if depth < needs {
+			return Err(ErrorKind::Underflow);
+		}
+		state.depth = after;
+		self.max_stack = self.max_stack.max(after);
+
+		let instr = match *token {
+			Token::Operand(ref x) => Instr::Push(x.clone()),
+			Token::Operator(op) => Instr::Op(op),
+			Token::Stack(op) => Instr::Stack(op),
+			Token::Var(ref name) => {
+				let slot = self.slot(name, &mut state.stored);
+				if !state.stored[slot] && self.first_read[slot].is_none() {
+					self.first_read[slot] = Some(index);
+				}
+				Instr::Load(slot)
+			}
+			Token::Store(ref name) => {
+				let slot = self.slot(name, &mut state.stored);
+				state.stored[slot] = true;
+				Instr::Store(slot)
+			}
+			Token::If => {
+				state.branches.push(Branch { at: self.code.len(), else_at: None, depth: after, then_depth: 0 });
+				// the target is filled in by the 'else' or 'then'
+				Instr::If(0)
+			}
+			Token::Call(_) | Token::Else | Token::Then => unreachable!("handled above"),
+		};
+		self.push(instr, index);
+		Ok(())
+	}
+
+	// Compiles an 'else' or 'then', checking the depths of the branches it ends.
+	fn close(&mut self, token: &Token<T>, index: usize, state: &mut State) -> Result<(), ErrorKind> {
+		if state.branches.len() <= state.outer {
+			return Err(ErrorKind::UnmatchedConditional);
+		}
+		let mut branch = state.branches.pop().unwrap();
+		let here = self.code.len();
+		if let Token::Else = *token {
+			if branch.else_at.is_some() {
+				return Err(ErrorKind::UnmatchedConditional);
+			}
+			self.code[branch.at] = Instr::If(here + 1);
+			branch.else_at = Some(here);
+			branch.then_depth = state.depth;
+			state.depth = branch.depth;
+			state.branches.push(branch);
+			// the target is filled in by the 'then'
+			self.push(Instr::Else(0), index);
+			return Ok(());
+		}
+		// without an 'else' the skipped branch leaves the depth the 'if' had
+		let expected = match branch.else_at {
+			Some(at) => {
+				self.code[at] = Instr::Else(here);
+				branch.then_depth
+			}
+			None => {
+				self.code[branch.at] = Instr::If(here);
+				branch.depth
+			}
+		};
+		if state.depth != expected {
+			return Err(ErrorKind::UnbalancedBranches);
+		}
+		self.push(Instr::Then, index);
+		Ok(())
+	}
+
+	fn push(&mut self, instr: Instr<T>, index: usize) {
+		self.code.push(instr);
+		self.origin.push(index);
+	}
+
+	// Returns the slot of the variable 'name', adding one if needed.
+	fn slot(&mut self, name: &str, stored: &mut Vec<bool>) -> usize {
+		match self.names.iter().position(|n| n == name) {
+			Some(slot) => slot,
+			None => {
+				self.names.push(name.to_string());
+				self.first_read.push(None);
+				stored.push(false);
+				self.names.len() - 1
+			}
+		}
+	}
+
+	/// Returns the names of the variables the program reads or stores, indexed by slot.
+	pub fn variables(&self) -> &[String] {
+		&self.names
+	}
+
+	/// Returns the names of the variables that must be bound before evaluation, because
+	/// the program reads them before storing into them.
+	pub fn inputs(&self) -> Vec<&str> {
+		self.names.iter().zip(&self.first_read)
+			.filter(|&(_, read)| read.is_some())
+			.map(|(name, _)| &name[..])
+			.collect()
+	}
+
+	/// Returns the largest number of values the stack holds during evaluation.
+	pub fn max_stack(&self) -> usize {
+		self.max_stack
+	}
+
+	/// Evaluates the program, reading variables from 'env' and writing stores back to it.
+	///
+	/// A missing input variable fails with 'ErrorKind::UndefinedVariable' before anything
+	/// runs, as does reading a variable only stored in a branch that did not run. 'env'
+	/// is only updated if evaluation succeeds.
+	pub fn eval(&self, env: &mut Env<T>, options: &EvalOptions) -> Result<T, EvalError> {
+		let mut slots = Vec::with_capacity(self.names.len());
+		for (name, read) in self.names.iter().zip(&self.first_read) {
+			match (env.get(name), *read) {
+				(Some(x), _) => slots.push(Some(x.clone())),
+				(None, Some(index)) => return Err(EvalError { index, kind: ErrorKind::UndefinedVariable }),
+				(None, None) => slots.push(None),
+			}
+		}
+		let result = self.run(&mut slots, options)?;
+		for (name, x) in self.names.iter().zip(slots) {
+			if let Some(x) = x {
+				env.insert(name.clone(), x);
+			}
+		}
+		Ok(result)
+	}
+
+	/// Evaluates the program with variables given by slot, as listed by 'variables()'.
+	///
+	/// Every input slot must be 'Some', and 'slots' must have one slot per variable, so
+	/// stores have somewhere to go; otherwise evaluation fails with
+	/// 'ErrorKind::UndefinedVariable' before anything runs. Stored values are written back
+	/// to 'slots'.
+	pub fn eval_slots(&self, slots: &mut [Option<T>], options: &EvalOptions) -> Result<T, EvalError> {
+		for (slot, read) in self.first_read.iter().enumerate() {
+			if let Some(index) = *read {
+				if slots.get(slot).is_none_or(|x| x.is_none()) {
+					return Err(EvalError { index, kind: ErrorKind::UndefinedVariable });
+				}
+			}
+		}
+		if slots.len() < self.names.len() {
+			// the first token to use a variable without a slot
+			let pc = self.code.iter()
+				.position(|instr| matches!(*instr, Instr::Load(slot) | Instr::Store(slot) if slot >= slots.len()))
+				.unwrap();
+			return Err(EvalError { index: self.origin[pc], kind: ErrorKind::UndefinedVariable });
+		}
+		self.run(slots, options)
+	}
+
+	/// Evaluates the program once per row of 'columns', giving a column of results.
+	///
+	/// 'columns' holds one column per name in 'inputs()', in the same order. The program
+	/// runs one instruction at a time over whole columns rather than one row at a time;
+	/// each branch of a conditional runs over the rows that take it. A row that fails
+	/// keeps its first error and is skipped by later instructions; the other rows carry
+	/// on. A program without inputs has no rows, so gives no results.
+	///
+	/// # Panics
+	///
+	/// Panics if the number of columns does not match 'inputs()', or if the columns have
+	/// different lengths.
+	pub fn eval_batch<C: AsRef<[T]>>(&self, columns: &[C], options: &EvalOptions) -> Vec<Result<T, EvalError>> {
+		let inputs = self.first_read.iter().filter(|read| read.is_some()).count();
+		assert_eq!(inputs, columns.len(), "expected one column per input variable");
+		let rows = columns.first().map_or(0, |c| c.as_ref().len());
+		assert!(columns.iter().all(|c| c.as_ref().len() == rows), "columns have different lengths");
+
+		// every value is 'None' in the rows that are not running, or have failed
+		let mut columns = columns.iter();
+		let mut slots: Vec<Vec<Option<T>>> = self.first_read.iter()
+			.map(|read| match *read {
+				Some(_) => columns.next().unwrap().as_ref().iter().cloned().map(Some).collect(),
+				None => vec![None; rows],
+			})
+			.collect();
+		let mut errors: Vec<Option<EvalError>> = vec![None; rows];
+		// the rows running the current branch
+		let mut active = vec![true; rows];
+		let mut branches: Vec<BatchBranch<T>> = Vec::new();
+		let mut v: Vec<Vec<Option<T>>> = Vec::with_capacity(self.max_stack);
+		// the arguments of one row of an operator, reused for every row
+		let mut row_args: Vec<T> = Vec::new();
+		for (pc, instr) in self.code.iter().enumerate() {
+			let fail = |errors: &mut Vec<Option<EvalError>>, row: usize, kind| {
+				errors[row] = Some(EvalError { index: self.origin[pc], kind });
+				None
+			};
+			match *instr {
+				Instr::Push(ref x) => v.push(active.iter().map(|&a| if a { Some(x.clone()) } else { None }).collect()),
+				Instr::Op(ref op) => {
+					let n = op.arity();
+					let args = v.split_off(v.len() - n);
+					let mut out = Vec::with_capacity(rows);
+					for row in 0..rows {
+						row_args.clear();
+						if active[row] && errors[row].is_none() {
+							row_args.extend(args.iter().filter_map(|c| c[row].clone()));
+						}
+						out.push(if row_args.len() < n {
+							None
+						} else {
+							match T::apply(op, &row_args, options.overflow) {
+								Ok(x) => Some(x),
+								Err(kind) => fail(&mut errors, row, kind),
+							}
+						});
+					}
+					v.push(out);
+				}
+				Instr::Stack(ref op) => op.run(&mut v),
+				Instr::Load(slot) => {
+					let mut out = Vec::with_capacity(rows);
+					for row in 0..rows {
+						out.push(match slots[slot][row] {
+							_ if !active[row] || errors[row].is_some() => None,
+							Some(ref x) => Some(x.clone()),
+							None => fail(&mut errors, row, ErrorKind::UndefinedVariable),
+						});
+					}
+					v.push(out);
+				}
+				Instr::Store(slot) => {
+					let top = v.pop().unwrap();
+					for (row, x) in top.into_iter().enumerate() {
+						if active[row] && errors[row].is_none() {
+							slots[slot][row] = x;
+						}
+					}
+				}
+				Instr::If(_) => {
+					let cond: Vec<bool> = v.pop().unwrap().iter()
+						.map(|x| x.as_ref().is_some_and(Number::is_true))
+						.collect();
+					let parent = active.clone();
+					for (a, &c) in active.iter_mut().zip(&cond) {
+						*a = *a && c;
+					}
+					branches.push(BatchBranch { parent, cond, saved: v.clone(), then_v: None });
+				}
+				Instr::Else(_) => {
+					let branch = branches.last_mut().unwrap();
+					branch.then_v = Some(mem::replace(&mut v, mem::take(&mut branch.saved)));
+					for ((a, &p), &c) in active.iter_mut().zip(&branch.parent).zip(&branch.cond) {
+						*a = p && !c;
+					}
+				}
+				Instr::Then => {
+					let BatchBranch { parent, cond, saved, then_v } = branches.pop().unwrap();
+					// without an 'else' the rows that skipped the branch keep the stack
+					// from before it
+					let (then_v, else_v) = match then_v {
+						Some(then_v) => (then_v, mem::take(&mut v)),
+						None => (mem::take(&mut v), saved),
+					};
+					v = then_v.into_iter().zip(else_v)
+						.map(|(t, e)| t.into_iter().zip(e).zip(&cond)
+							.map(|((t, e), &c)| if c { t } else { e })
+							.collect())
+						.collect();
+					active = parent;
+				}
+			}
+		}
+		let results = v.pop().unwrap_or_default();
+		results.into_iter().zip(errors)
+			.map(|(x, e)| match e {
+				Some(e) => Err(e),
+				None => Ok(x.unwrap()),
+			})
+			.collect()
+	}
+
+	// Runs the code. The depths were checked when compiling, so only the values can fail.
+	fn run(&self, slots: &mut [Option<T>], options: &EvalOptions) -> Result<T, EvalError> {
+		let mut v: Vec<T> = Vec::with_capacity(self.max_stack);
+		let mut pc = 0;
+		while pc < self.code.len() {
+			match self.code[pc] {
+				Instr::Push(ref x) => v.push(x.clone()),
+				Instr::Op(ref op) => {
+					let base = v.len() - op.arity();
+					let x = T::apply(op, &v[base..], options.overflow)
+						.map_err(|kind| EvalError { index: self.origin[pc], kind })?;
+					v.truncate(base);
+					v.push(x);
+				}
+				Instr::Stack(ref op) => op.run(&mut v),
+				Instr::Load(slot) => match slots[slot] {
+					Some(ref x) => v.push(x.clone()),
+					// only stored in a branch that did not run
+					None => return Err(EvalError { index: self.origin[pc], kind: ErrorKind::UndefinedVariable }),
+				},
+				Instr::Store(slot) => slots[slot] = v.pop(),
+				Instr::If(target) => if !v.pop().unwrap().is_true() {
+					pc = target;
+					continue;
+				},
+				Instr::Else(target) => {
+					pc = target;
+					continue;
+				}
+				Instr::Then => {}
+			}
+			pc += 1;
+		}
+		Ok(v.pop().unwrap())
+	}
+}
+
+// A conditional being run by 'eval_batch'.
+struct BatchBranch<T> {
+	// the rows running when the 'if' was reached, and those whose condition was true
+	parent: Vec<bool>,
+	cond: Vec<bool>,
+	// the stack after the condition was popped, for the 'else' branch
+	saved: Vec<Vec<Option<T>>>,
+	// the stack at the end of the 'if' branch, once the 'else' is reached
+	then_v: Option<Vec<Vec<Option<T>>>>,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use {eval_with_env, eval_with_words, parse_postfix, parse_program};
+
+	#[test]
+	fn compiles_and_evaluates() {
+		let tokens = parse_postfix("x x * y 3 pick(1) + * +").unwrap();
+		let program = CompiledProgram::new(&tokens).unwrap();
+		assert_eq!(4, program.max_stack());
+		assert_eq!(vec!["x", "y"], program.inputs());
+		let options = EvalOptions::default();
+		for x in -3..4 {
+			let mut env = Env::new();
+			env.insert("x".to_string(), x);
+			env.insert("y".to_string(), 2);
+			assert_eq!(eval_with_env(&tokens, &mut env.clone(), &options), program.eval(&mut env, &options));
+		}
+	}
+
+	#[test]
+	fn rejects_bad_depths_up_front() {
+		let compile = |text| CompiledProgram::new(&parse_postfix(text).unwrap());
+		assert_eq!(Err(EvalError { index: 3, kind: ErrorKind::Underflow }), compile("1 2 + * 1"));
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::TrailingOperands }), compile("1 2"));
+		assert_eq!(Err(EvalError { index: 3, kind: ErrorKind::Underflow }), compile("1 2 clear"));
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::Underflow }), compile("1 2 rot"));
+		assert_eq!(Err(EvalError { index: 0, kind: ErrorKind::Underflow }), compile("->x 1"));
+		assert_eq!(Err(EvalError { index: 0, kind: ErrorKind::Underflow }), compile(""));
+	}
+
+	#[test]
+	fn value_errors_at_run_time() {
+		let program = CompiledProgram::new(&parse_postfix("1 x /").unwrap()).unwrap();
+		let options = EvalOptions::default();
+		let mut env = Env::new();
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::UndefinedVariable }), program.eval(&mut env, &options));
+		env.insert("x".to_string(), 0);
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::DivideByZero }), program.eval(&mut env, &options));
+		assert_eq!(Ok(1), program.eval_slots(&mut [Some(1)], &options));
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::UndefinedVariable }), program.eval_slots(&mut [], &options));
+
+		// a slot only stored to still has to be there
+		let program = CompiledProgram::new(&parse_postfix("x ->y y").unwrap()).unwrap();
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::UndefinedVariable }), program.eval_slots(&mut [Some(1)], &options));
+		assert_eq!(Ok(1), program.eval_slots(&mut [Some(1), None], &options));
+	}
+
+	#[test]
+	fn stores_and_words() {
+		let mut words = Dictionary::new();
+		let tokens = parse_program(": square dup * ; 3 square ->t t t +", &mut words).unwrap();
+		let program = CompiledProgram::with_words(&tokens, &words).unwrap();
+		assert_eq!(vec!["t"], program.variables());
+		assert!(program.inputs().is_empty());
+		let mut env = Env::new();
+		assert_eq!(Ok(18), program.eval(&mut env, &EvalOptions::default()));
+		assert_eq!(Some(&9), env.get("t"));
+
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::UndefinedWord }), CompiledProgram::new(&tokens));
+		let tokens = parse_program(": forever forever ; forever", &mut words).unwrap();
+		assert_eq!(Err(EvalError { index: 0, kind: ErrorKind::RecursionLimit }), CompiledProgram::with_words(&tokens, &words));
+	}
+
+	#[test]
+	fn evaluates_columns() {
+		let program = CompiledProgram::new(&parse_postfix("x y / ->q q q *").unwrap()).unwrap();
+		assert_eq!(vec!["x", "y"], program.inputs());
+		let xs = vec![6, 7, 8, 9];
+		let ys = vec![3, 0, -2, 1];
+		let options = EvalOptions::default();
+		let results = program.eval_batch(&[xs.clone(), ys.clone()], &options);
+		assert_eq!(vec![Ok(4), Err(EvalError { index: 2, kind: ErrorKind::DivideByZero }), Ok(16), Ok(81)], results);
+		// slices work too, and every row agrees with evaluating it on its own
+		for (row, result) in program.eval_batch(&[&xs[..], &ys[..]], &options).into_iter().enumerate() {
+			assert_eq!(program.eval_slots(&mut [Some(xs[row]), Some(ys[row]), None], &options), result);
+		}
+		assert!(program.eval_batch(&[Vec::new(), Vec::new()], &options).is_empty());
+
+		// with no inputs there are no rows
+		let program = CompiledProgram::new(&parse_postfix("2 3 ^ dup *").unwrap()).unwrap();
+		assert!(program.eval_batch::<Vec<isize>>(&[], &options).is_empty());
+	}
+
+	#[test]
+	fn checks_both_branches() {
+		let compile = |text| CompiledProgram::new(&parse_postfix(text).unwrap()).map_err(|e| (e.index, e.kind));
+		assert!(compile("x 0 < if x neg else x then").is_ok());
+		assert!(compile("x 0 < if 0 ->x then x").is_ok());
+		assert_eq!(Err((8, ErrorKind::UnbalancedBranches)), compile("x 0 < if x else x x then"));
+		assert_eq!(Err((4, ErrorKind::UnbalancedBranches)), compile("x 1 if 2 then +"));
+		assert_eq!(Err((2, ErrorKind::UnmatchedConditional)), compile("x 1 if 2 else"));
+		assert_eq!(Err((1, ErrorKind::UnmatchedConditional)), compile("1 then"));
+		assert_eq!(Err((0, ErrorKind::Underflow)), compile("if 1 then"));
+
+		let mut words = Dictionary::new();
+		let tokens = parse_program(": open if ; 1 1 open 2 then", &mut words).unwrap();
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::UnmatchedConditional }), CompiledProgram::with_words(&tokens, &words));
+
+		// nor can a word close one opened by its caller, just as 'eval' rejects it
+		for text in &[": e else ; 1 if 2 e 3 then", ": t then ; 1 if 2 t"] {
+			let tokens = parse_program(text, &mut words).unwrap();
+			let compiled = CompiledProgram::with_words(&tokens, &words).err().map(|e| e.kind);
+			assert_eq!(Some(ErrorKind::UnmatchedConditional), compiled, "{}", text);
+			assert_eq!(eval_with_words(&tokens, &mut Env::new(), &words, &EvalOptions::default()).err().map(|e| e.kind), compiled, "{}", text);
+		}
+	}
+
+	#[test]
+	fn runs_branches() {
+		let tokens = parse_postfix("x 0 < if x neg ->y else x 10 > if 10 ->y else x ->y then then y 100 x / +").unwrap();
+		let program = CompiledProgram::new(&tokens).unwrap();
+		assert_eq!(vec!["x"], program.inputs());
+		let options = EvalOptions::default();
+		let xs = vec![-4, 0, 7, 25];
+		let batch = program.eval_batch(&[&xs[..]], &options);
+		assert_eq!(Err(EvalError { index: 22, kind: ErrorKind::DivideByZero }), batch[1]);
+		for (x, result) in xs.into_iter().zip(batch) {
+			let mut env = Env::new();
+			env.insert("x".to_string(), x);
+			assert_eq!(eval_with_env(&tokens, &mut env.clone(), &options), result);
+			assert_eq!(result, program.eval(&mut env, &options));
+		}
+
+		// 'y' is only stored when the branch runs
+		let program = CompiledProgram::new(&parse_postfix("x if 1 ->y then y").unwrap()).unwrap();
+		assert_eq!(vec![Ok(1), Err(EvalError { index: 5, kind: ErrorKind::UndefinedVariable })], program.eval_batch(&[vec![3, 0]], &options));
+		assert_eq!(Err(EvalError { index: 5, kind: ErrorKind::UndefinedVariable }), program.eval_slots(&mut [Some(0), None], &options));
+	}
+
+	#[test]
+	#[should_panic(expected = "one column per input")]
+	fn batch_needs_every_input() {
+		let program = CompiledProgram::new(&parse_postfix("x y +").unwrap()).unwrap();
+		program.eval_batch(&[vec![1]], &EvalOptions::default());
+	}
+}
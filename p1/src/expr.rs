@@ -0,0 +1,178 @@
+use p3::{self, InfixToken, ParseError};
+
+use {single_value, to_infix_tokens, Env, ErrorKind, EvalError, EvalOptions, Number, Operator, Token};
+
+/// An expression tree, e.g. 'x 2 * 1 +' is 'Binary(Add, Binary(Mul, Var(x), Literal(2)), Literal(1))'.
+///
+/// 'Unary' holds an operator of arity 1 and 'Binary' one of arity 2, as 'from_postfix'
+/// builds them. Printing with 'Display' gives infix notation, as 'to_infix' does.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr<T = isize> {
+	Literal(T),
+	Var(String),
+	Unary(Operator, Box<Expr<T>>),
+	Binary(Operator, Box<Expr<T>>, Box<Expr<T>>),
+}
+
+impl<T> Expr<T> {
+	/// Returns the variable 'name'.
+	pub fn var(name: &str) -> Expr<T> {
+		Expr::Var(name.to_string())
+	}
+
+	/// Returns 'op' applied to 'y'.
+	pub fn unary(op: Operator, y: Expr<T>) -> Expr<T> {
+		Expr::Unary(op, Box::new(y))
+	}
+
+	/// Returns 'op' applied to 'z' and 'y', written 'z y op' in postfix.
+	pub fn binary(op: Operator, z: Expr<T>, y: Expr<T>) -> Expr<T> {
+		Expr::Binary(op, Box::new(z), Box::new(y))
+	}
+}
+
+impl<T: Clone> Expr<T> {
+	/// Builds the tree of a postfix expression.
+	///
+	/// Stack words, stores, word calls and conditionals have no tree form and fail with
+	/// 'ErrorKind::Unsupported'; a malformed expression fails as 'eval' would, with
+	/// 'ErrorKind::Underflow' or 'ErrorKind::TrailingOperands'.
+	pub fn from_postfix(tokens: &[Token<T>]) -> Result<Expr<T>, EvalError> {
+		let mut v = Vec::new();
+		for (index, token) in tokens.iter().enumerate() {
+			let err = |kind| EvalError { index, kind };
+			match *token {
+				Token::Operand(ref x) => v.push(Expr::Literal(x.clone())),
+				Token::Var(ref name) => v.push(Expr::Var(name.clone())),
+				Token::Operator(op) => {
+					if v.len() < op.arity() {
+						return Err(err(ErrorKind::Underflow));
+					}
+					let y = v.pop().unwrap();
+					if op.arity() == 1 {
+						v.push(Expr::unary(op, y));
+					} else {
+						let z = v.pop().unwrap();
+						v.push(Expr::binary(op, z, y));
+					}
+				}
+				_ => return Err(err(ErrorKind::Unsupported)),
+			}
+		}
+		single_value(v, tokens.len())
+	}
+
+	/// Returns the expression in postfix notation.
+	pub fn to_postfix(&self) -> Vec<Token<T>> {
+		let mut out = Vec::new();
+		self.push_postfix(&mut out);
+		out
+	}
+
+	fn push_postfix(&self, out: &mut Vec<Token<T>>) {
+		match *self {
+			Expr::Literal(ref x) => out.push(Token::Operand(x.clone())),
+			Expr::Var(ref name) => out.push(Token::Var(name.clone())),
+			Expr::Unary(op, ref y) => {
+				y.push_postfix(out);
+				out.push(Token::Operator(op));
+			}
+			Expr::Binary(op, ref z, ref y) => {
+				z.push_postfix(out);
+				y.push_postfix(out);
+				out.push(Token::Operator(op));
+			}
+		}
+	}
+}
+
+impl<T: Number> Expr<T> {
+	/// Evaluates the tree, reading variables from 'env'.
+	///
+	/// Fails with the first error met going left to right, as evaluating 'to_postfix'
+	/// would, but without a token index. An operator under the wrong variant fails with
+	/// 'ErrorKind::Unsupported'. 'OverflowPolicy::Arbitrary' is applied to each operation,
+	/// as with 'exec'.
+	pub fn eval(&self, env: &Env<T>, options: &EvalOptions) -> Result<T, ErrorKind> {
+		match *self {
+			Expr::Literal(ref x) => Ok(x.clone()),
+			Expr::Var(ref name) => env.get(name).cloned().ok_or(ErrorKind::UndefinedVariable),
+			Expr::Unary(ref op, ref y) if op.arity() == 1 => T::apply(op, &[y.eval(env, options)?], options.overflow),
+			Expr::Binary(ref op, ref z, ref y) if op.arity() == 2 => {
+				let args = [z.eval(env, options)?, y.eval(env, options)?];
+				T::apply(op, &args, options.overflow)
+			}
+			_ => Err(ErrorKind::Unsupported),
+		}
+	}
+}
+
+impl Expr {
+	/// Builds the tree of a p3 infix expression, failing with p3's error if it cannot be parsed.
+	pub fn from_infix(tokens: &[InfixToken]) -> Result<Expr, ParseError> {
+		let postfix: Vec<Token> = p3::infix_to_postfix(tokens)?.into_iter().map(Token::from).collect();
+		Ok(Expr::from_postfix(&postfix).expect("p3 only accepts well-formed expressions"))
+	}
+
+	/// Returns the expression as p3 infix tokens, with as few parentheses as possible.
+	///
+	/// Only literals, variables, '+ - * / ^', 'neg' and p3's functions 'abs', 'min' and
+	/// 'max' have p3 tokens; anything else fails with 'ErrorKind::Unsupported'.
+	pub fn to_infix_tokens(&self) -> Result<Vec<InfixToken>, ErrorKind> {
+		to_infix_tokens(&self.to_postfix()).map_err(|e| e.kind)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use parse_postfix;
+
+	fn tree(text: &str) -> Expr {
+		Expr::from_postfix(&parse_postfix(text).unwrap()).unwrap()
+	}
+
+	#[test]
+	fn builds_from_postfix() {
+		let e = Expr::binary(Operator::Add, Expr::binary(Operator::Mul, Expr::var("x"), Expr::Literal(2)), Expr::Literal(1));
+		assert_eq!(e, tree("x 2 * 1 +"));
+		assert_eq!(Expr::unary(Operator::Neg, Expr::var("x")), tree("x neg"));
+		assert_eq!(parse_postfix("x 2 * 1 + abs y max").unwrap(), tree("x 2 * 1 + abs y max").to_postfix());
+
+		let err = |text| Expr::from_postfix(&parse_postfix(text).unwrap());
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::Underflow }), err("1 +"));
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::TrailingOperands }), err("1 2"));
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::Unsupported }), err("1 dup +"));
+	}
+
+	#[test]
+	fn evaluates() {
+		let mut env = Env::new();
+		env.insert("x".to_string(), 5);
+		let options = EvalOptions::default();
+		assert_eq!(Ok(11), tree("x 2 * 1 +").eval(&env, &options));
+		assert_eq!(Ok(1), tree("x 3 > x 10 < and").eval(&env, &options));
+		assert_eq!(Err(ErrorKind::DivideByZero), tree("x 0 /").eval(&env, &options));
+		assert_eq!(Err(ErrorKind::UndefinedVariable), tree("y").eval(&env, &options));
+		assert_eq!(Err(ErrorKind::Unsupported), Expr::unary(Operator::Add, Expr::Literal(1)).eval(&env, &options));
+	}
+
+	#[test]
+	fn converts_to_and_from_p3() {
+		use p3::Operator::{Add, Mul};
+		// 1 + 2 * 3
+		let infix = vec![InfixToken::Operand(1), InfixToken::Operator(Add), InfixToken::Operand(2), InfixToken::Operator(Mul), InfixToken::Operand(3)];
+		let e = Expr::from_infix(&infix).unwrap();
+		assert_eq!(tree("1 2 3 * +"), e);
+		assert_eq!(Ok(infix), e.to_infix_tokens());
+		assert_eq!(Err(p3::ParseErrorKind::BadStart), Expr::from_infix(&[InfixToken::Operator(Mul)]).map_err(|e| e.kind));
+		assert_eq!(Ok(tree("1 2 x * max y - abs")), Expr::from_infix(&p3::tokenize("abs(max(1, 2 * x) - y)").unwrap()));
+		assert_eq!(Err(ErrorKind::Unsupported), tree("x 1 %").to_infix_tokens());
+	}
+
+	#[test]
+	fn prints_infix() {
+		assert_eq!("(x + 1) * -y", tree("x 1 + y neg *").to_string());
+		assert_eq!("max(x, 2 ^ 3 ^ 2)", tree("x 2 3 2 ^ ^ max").to_string());
+	}
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+
+use p3::{self, InfixToken, PostfixToken};
+
+use {single_value, ErrorKind, EvalError, Expr, Operator, Token};
+
+// Precedence levels, loosest first. Unary minus sits between '*' and '^', so '-x ^ 2'
+// is '-(x ^ 2)'.
+const OR: u8 = 1;
+const AND: u8 = 2;
+const COMPARE: u8 = 3;
+const SUM: u8 = 4;
+const PRODUCT: u8 = 5;
+const UNARY: u8 = 6;
+const POWER: u8 = 7;
+const ATOM: u8 = 8;
+
+// Returns the precedence of a binary infix operator, and whether it is right-associative.
+fn binary(op: &Operator) -> Option<(u8, bool)> {
+	match *op {
+		Operator::Add | Operator::Sub => Some((SUM, false)),
+		Operator::Mul | Operator::Div | Operator::Rem => Some((PRODUCT, false)),
+		Operator::Pow => Some((POWER, true)),
+		Operator::Eq | Operator::Ne | Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge => Some((COMPARE, false)),
+		Operator::And => Some((AND, false)),
+		Operator::Or => Some((OR, false)),
+		_ => None,
+	}
+}
+
+// Returns whether an operand of precedence 'child' needs parentheses on the 'right' or
+// left of a binary operator with precedence 'prec'. At equal precedence only the side
+// the operator groups towards goes without, so 'z - y' and 'z - (y - x)' keep their order.
+fn needs_parens(prec: u8, right_assoc: bool, child: u8, right: bool) -> bool {
+	child < prec || (child == prec && right != right_assoc)
+}
+
+/// Prints the postfix expression in infix notation with as few parentheses as possible.
+///
+/// Binary operators are printed between their operands, 'neg' as a leading '-', and
+/// 'abs', 'min', 'max' and 'not' as calls such as 'max(x, 2)'. From loosest to tightest,
+/// the levels are 'or', 'and', the comparisons, '+ -', '* / %', a leading '-', and '^'.
+/// '^' groups to the right and the others to the left. Stack words, stores, word calls
+/// and conditionals have no infix form and fail with 'ErrorKind::Unsupported'; a malformed
+/// expression fails as 'eval' would, with 'ErrorKind::Underflow' or
+/// 'ErrorKind::TrailingOperands'.
+pub fn to_infix<T: Clone + fmt::Display>(tokens: &[Token<T>]) -> Result<String, EvalError> {
+	Expr::from_postfix(tokens).map(|e| e.to_string())
+}
+
+/// Prints the expression as 'to_infix' does.
+impl<T: fmt::Display> fmt::Display for Expr<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", text(self).0)
+	}
+}
+
+// Returns the text of an expression and its precedence.
+fn text<T: fmt::Display>(e: &Expr<T>) -> (String, u8) {
+	match *e {
+		Expr::Literal(ref x) => {
+			let s = x.to_string();
+			let prec = if s.starts_with('-') { UNARY } else { ATOM };
+			(s, prec)
+		}
+		Expr::Var(ref name) => (name.clone(), ATOM),
+		Expr::Binary(ref op, ref z, ref y) if binary(op).is_some() => {
+			let (prec, right_assoc) = binary(op).unwrap();
+			let side = |e, right| {
+				let (s, child) = text(e);
+				if needs_parens(prec, right_assoc, child, right) {
+					format!("({})", s)
+				} else {
+					s
+				}
+			};
+			(format!("{} {} {}", side(z, false), op, side(y, true)), prec)
+		}
+		Expr::Unary(Operator::Neg, ref y) => {
+			// '-(-x)' rather than '--x'
+			let (s, child) = text(y);
+			if child <= UNARY {
+				(format!("-({})", s), UNARY)
+			} else {
+				(format!("-{}", s), UNARY)
+			}
+		}
+		Expr::Unary(ref op, ref y) => (format!("{}({})", op, text(y).0), ATOM),
+		Expr::Binary(ref op, ref z, ref y) => (format!("{}({}, {})", op, text(z).0, text(y).0), ATOM),
+	}
+}
+
+/// Converts the postfix expression to p3 infix tokens, with as few parentheses as possible.
+///
+/// Only operands, variables, '+ - * / ^', 'neg' and p3's functions 'abs', 'min' and
+/// 'max' have p3 tokens; anything else fails with 'ErrorKind::Unsupported'. Passing the
+/// result to 'p3::infix_to_postfix' gives back 'tokens'.
+pub fn to_infix_tokens(tokens: &[Token]) -> Result<Vec<InfixToken>, EvalError> {
+	let functions = p3::OperatorTable::new();
+	let mut v: Vec<(Vec<InfixToken>, u8)> = Vec::new();
+	for (index, token) in tokens.iter().enumerate() {
+		let err = |kind| EvalError { index, kind };
+		match *token {
+			// p3 operands are single tokens, even when negative, but p3 reads '-3 ^ 2' as
+			// '-(3 ^ 2)', so a negative one binds like unary minus and gets parentheses under '^'
+			Token::Operand(x) => v.push((vec![InfixToken::Operand(x)], if x < 0 { UNARY } else { ATOM })),
+			Token::Var(ref name) => v.push((vec![InfixToken::Var(name.clone())], ATOM)),
+			Token::Operator(ref op) if functions.function(op.symbol()) == Some(op.arity()) => {
+				if v.len() < op.arity() {
+					return Err(err(ErrorKind::Underflow));
+				}
+				let args = v.split_off(v.len() - op.arity());
+				let mut call = vec![InfixToken::Function(op.symbol().to_string()), InfixToken::LeftParen];
+				for (i, (arg, _)) in args.into_iter().enumerate() {
+					if i > 0 {
+						call.push(InfixToken::Comma);
+					}
+					call.extend(arg);
+				}
+				call.push(InfixToken::RightParen);
+				v.push((call, ATOM));
+			}
+			Token::Operator(ref op) => {
+				let p3_op = to_p3(op).ok_or_else(|| err(ErrorKind::Unsupported))?;
+				if v.len() < op.arity() {
+					return Err(err(ErrorKind::Underflow));
+				}
+				if *op == Operator::Neg {
+					let (right, right_prec) = v.pop().unwrap();
+					let mut out = vec![InfixToken::Operator(p3_op)];
+					if right_prec <= UNARY {
+						out.push(InfixToken::LeftParen);
+						out.extend(right);
+						out.push(InfixToken::RightParen);
+					} else {
+						out.extend(right);
+					}
+					v.push((out, UNARY));
+					continue;
+				}
+				if v.len() < 2 {
+					return Err(err(ErrorKind::Underflow));
+				}
+				let (prec, right_assoc) = binary(op).unwrap();
+				let (right, right_prec) = v.pop().unwrap();
+				let (mut left, left_prec) = v.pop().unwrap();
+				if needs_parens(prec, right_assoc, left_prec, false) {
+					left.insert(0, InfixToken::LeftParen);
+					left.push(InfixToken::RightParen);
+				}
+				left.push(InfixToken::Operator(p3_op));
+				if needs_parens(prec, right_assoc, right_prec, true) {
+					left.push(InfixToken::LeftParen);
+					left.extend(right);
+					left.push(InfixToken::RightParen);
+				} else {
+					left.extend(right);
+				}
+				v.push((left, prec));
+			}
+			_ => return Err(err(ErrorKind::Unsupported)),
+		}
+	}
+	single_value(v, tokens.len()).map(|(t, _)| t)
+}
+
+fn to_p3(op: &Operator) -> Option<p3::Operator> {
+	match *op {
+		Operator::Add => Some(p3::Operator::Add),
+		Operator::Sub => Some(p3::Operator::Sub),
+		Operator::Mul => Some(p3::Operator::Mul),
+		Operator::Div => Some(p3::Operator::Div),
+		Operator::Neg => Some(p3::Operator::Neg),
+		Operator::Pow => Some(p3::Operator::Pow),
+		_ => None,
+	}
+}
+
+/// Converts the output of 'p3::infix_to_postfix' into tokens 'eval' can run.
+///
+/// A 'p3::Operator::Custom', or a function call, becomes the operator with the same
+/// symbol and arity, or else a 'Call' of the word named by it, to be defined in the
+/// 'Dictionary'.
+impl From<PostfixToken> for Token {
+	fn from(token: PostfixToken) -> Token {
+		let op = match token {
+			PostfixToken::Operand(x) => return Token::Operand(x),
+			PostfixToken::Var(name) => return Token::Var(name),
+			PostfixToken::Function(name, arity) => return match Operator::from_symbol(&name) {
+				Some(op) if op.arity() == arity => Token::Operator(op),
+				_ => Token::Call(name),
+			},
+			PostfixToken::Operator(op) => op,
+		};
+		Token::Operator(match op {
+			p3::Operator::Add => Operator::Add,
+			p3::Operator::Sub => Operator::Sub,
+			p3::Operator::Mul => Operator::Mul,
+			p3::Operator::Div => Operator::Div,
+			p3::Operator::Neg => Operator::Neg,
+			p3::Operator::Pow => Operator::Pow,
+			p3::Operator::Custom(symbol) => match Operator::from_symbol(&symbol) {
+				Some(op) => op,
+				None => return Token::Call(symbol),
+			},
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use parse_postfix;
+
+	fn infix(text: &str) -> Result<String, EvalError> {
+		to_infix(&parse_postfix(text).unwrap())
+	}
+
+	#[test]
+	fn minimal_parentheses() {
+		assert_eq!(Ok("1 + 2 * 3".to_string()), infix("1 2 3 * +"));
+		assert_eq!(Ok("(1 + 2) * 3".to_string()), infix("1 2 + 3 *"));
+		assert_eq!(Ok("z - y - x".to_string()), infix("z y - x -"));
+		assert_eq!(Ok("z - (y - x)".to_string()), infix("z y x - -"));
+		assert_eq!(Ok("a / b * c % d".to_string()), infix("a b / c * d %"));
+		assert_eq!(Ok("2 ^ 3 ^ 2".to_string()), infix("2 3 2 ^ ^"));
+		assert_eq!(Ok("(2 ^ 3) ^ 2".to_string()), infix("2 3 ^ 2 ^"));
+	}
+
+	#[test]
+	fn unary_and_calls() {
+		assert_eq!(Ok("-x ^ 2".to_string()), infix("x 2 ^ neg"));
+		assert_eq!(Ok("(-x) ^ 2".to_string()), infix("x neg 2 ^"));
+		assert_eq!(Ok("(-3) ^ 2".to_string()), infix("-3 2 ^"));
+		assert_eq!(Ok("-(-x) * 2".to_string()), infix("x neg neg 2 *"));
+		assert_eq!(Ok("-(a + b)".to_string()), infix("a b + neg"));
+		assert_eq!(Ok("1 - -3".to_string()), infix("1 -3 -"));
+		assert_eq!(Ok("max(abs(x - y), 2 * 3)".to_string()), infix("x y - abs 2 3 * max"));
+		assert_eq!(Ok("x < 1 or x + 1 >= y and not(z)".to_string()), infix("x 1 < x 1 + y >= z not and or"));
+		assert_eq!(Ok("(a or b) and c".to_string()), infix("a b or c and"));
+	}
+
+	#[test]
+	fn errors() {
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::Underflow }), infix("1 +"));
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::TrailingOperands }), infix("1 2"));
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::Unsupported }), infix("1 dup +"));
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::Unsupported }), to_infix_tokens(&parse_postfix("1 2 %").unwrap()));
+	}
+
+	#[test]
+	fn round_trips_with_p3() {
+		for text in &["1 2 3 * +", "1 2 + 3 *", "9 5 - 2 -", "9 5 2 - -", "8 -2 / 3 4 * 5 - *", "7", "3 neg 2 *",
+			"4 1 2 + neg *", "-3 neg neg", "2 -3 neg -",
+			"2 3 2 ^ ^", "2 3 ^ 2 ^", "2 neg 2 ^", "2 2 ^ neg", "-2 2 ^", "x y - abs", "1 2 x * max 3 +", "x neg 1 min 2 ^"] {
+			let tokens = parse_postfix(text).unwrap();
+			let infix = to_infix_tokens(&tokens).unwrap();
+			let postfix: Vec<Token> = p3::infix_to_postfix(&infix).unwrap().into_iter().map(Token::from).collect();
+			assert_eq!(tokens, postfix);
+		}
+		let infix = to_infix_tokens(&parse_postfix("9 5 2 - -").unwrap()).unwrap();
+		assert_eq!(vec![InfixToken::Operand(9), InfixToken::Operator(p3::Operator::Sub), InfixToken::LeftParen,
+			InfixToken::Operand(5), InfixToken::Operator(p3::Operator::Sub), InfixToken::Operand(2), InfixToken::RightParen], infix);
+
+		// the tokens also round trip through their text
+		for text in &["-3 2 ^", "2 -3 ^", "-3 neg 2 ^", "-2 3 *", "x -1 max abs"] {
+			let tokens = parse_postfix(text).unwrap();
+			let line = to_infix_tokens(&tokens).unwrap().iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" ");
+			let postfix: Vec<Token> = p3::infix_to_postfix(&p3::tokenize(&line).unwrap()).unwrap().into_iter().map(Token::from).collect();
+			assert_eq!(tokens, postfix, "{}", line);
+		}
+
+		// operators from a custom p3 table
+		assert_eq!(Token::Operator(Operator::Rem), Token::from(PostfixToken::Operator(p3::Operator::Custom("%".to_string()))));
+		assert_eq!(Token::Call("gcd".to_string()), Token::from(PostfixToken::Operator(p3::Operator::Custom("gcd".to_string()))));
+		// calls of functions from a custom table
+		assert_eq!(Token::Operator(Operator::Max), Token::from(PostfixToken::Function("max".to_string(), 2)));
+		assert_eq!(Token::Call("max".to_string()), Token::from(PostfixToken::Function("max".to_string(), 3)));
+	}
+}
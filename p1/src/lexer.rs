@@ -0,0 +1,256 @@
+use std::{error, fmt};
+
+use {Dictionary, Operator, StackOp, Token};
+
+/// The reason a word of postfix text could not be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LexErrorKind {
+	/// The word is not a number, an operator, a stack word or a variable name.
+	UnknownToken(String),
+	/// The word is a number that does not fit in an 'isize'.
+	OutOfRange(String),
+	/// The name after ':' is missing or is not a valid word name.
+	InvalidWordName(String),
+	/// The word after ':' is already in the dictionary.
+	Redefined(String),
+	/// A ':' appeared inside a definition.
+	NestedDefinition,
+	/// A ':' definition has no closing ';'.
+	UnterminatedDefinition,
+	/// A ';' appeared outside a definition.
+	UnmatchedSemicolon,
+}
+
+/// An error from reading postfix text.
+///
+/// 'column' is the 1-based character column where the offending word starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LexError {
+	pub column: usize,
+	pub kind: LexErrorKind,
+}
+
+impl fmt::Display for LexError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.kind {
+			LexErrorKind::UnknownToken(ref word) => write!(f, "unknown token '{}' at column {}", word, self.column),
+			LexErrorKind::OutOfRange(ref word) => write!(f, "number '{}' out of range at column {}", word, self.column),
+			LexErrorKind::InvalidWordName(ref word) => write!(f, "invalid word name '{}' at column {}", word, self.column),
+			LexErrorKind::Redefined(ref word) => write!(f, "word '{}' is already defined at column {}", word, self.column),
+			LexErrorKind::NestedDefinition => write!(f, "nested ':' definition at column {}", self.column),
+			LexErrorKind::UnterminatedDefinition => write!(f, "definition missing ';' at column {}", self.column),
+			LexErrorKind::UnmatchedSemicolon => write!(f, "';' outside a definition at column {}", self.column),
+		}
+	}
+}
+
+impl error::Error for LexError {}
+
+/// Reads a whitespace-separated postfix expression such as "-1 2 3 + *".
+///
+/// A '-' on its own is the subtraction operator; a '-' followed by digits is a negative literal.
+/// Any other name made of letters, digits and '_' is a variable, and '->name' stores into it.
+/// Returns: the tokens in order, or the first word that could not be read.
+pub fn parse_postfix(input: &str) -> Result<Vec<Token>, LexError> {
+	let mut tokens = Vec::new();
+	for (column, word) in words(input) {
+		tokens.push(parse_word(word).map_err(|kind| LexError { column, kind })?);
+	}
+	Ok(tokens)
+}
+
+/// Reads a postfix program that may define words, e.g. ": square dup * ; 3 square".
+///
+/// Each ': name body ;' is added to 'words' instead of the returned tokens. Names that
+/// refer to a word in 'words' (or, inside a body, to the word being defined) become
+/// 'Token::Call'; other names are variables as in 'parse_postfix', so a misspelled word is
+/// read as a variable ('Dictionary::unknown_names' finds those). A word must be defined
+/// before it is used and cannot be redefined.
+pub fn parse_program(input: &str, words: &mut Dictionary) -> Result<Vec<Token>, LexError> {
+	let mut tokens = Vec::new();
+	// the name, column and body of the definition being read
+	let mut def: Option<(&str, usize, Vec<Token>)> = None;
+	let mut iter = self::words(input).into_iter();
+	while let Some((column, word)) = iter.next() {
+		let err = |kind| LexError { column, kind };
+		match word {
+			":" => {
+				if def.is_some() {
+					return Err(err(LexErrorKind::NestedDefinition));
+				}
+				let (name_column, name) = iter.next().ok_or(err(LexErrorKind::UnterminatedDefinition))?;
+				if !is_name(name) || parse_word(name) != Ok(Token::Var(name.to_string())) {
+					return Err(LexError { column: name_column, kind: LexErrorKind::InvalidWordName(name.to_string()) });
+				}
+				if words.contains(name) {
+					return Err(LexError { column: name_column, kind: LexErrorKind::Redefined(name.to_string()) });
+				}
+				def = Some((name, column, Vec::new()));
+			}
+			";" => match def.take() {
+				Some((name, _, body)) => { words.define(name, body); }
+				None => return Err(err(LexErrorKind::UnmatchedSemicolon)),
+			},
+			_ => {
+				let token = match parse_word(word).map_err(err)? {
+					Token::Var(ref name) if words.contains(name) || def.as_ref().is_some_and(|d| d.0 == name) => {
+						Token::Call(name.clone())
+					}
+					token => token,
+				};
+				match def {
+					Some((_, _, ref mut body)) => body.push(token),
+					None => tokens.push(token),
+				}
+			}
+		}
+	}
+	if let Some((_, column, _)) = def {
+		return Err(LexError { column, kind: LexErrorKind::UnterminatedDefinition });
+	}
+	Ok(tokens)
+}
+
+fn parse_word(word: &str) -> Result<Token, LexErrorKind> {
+	if let Some(op) = Operator::from_symbol(word) {
+		return Ok(Token::Operator(op));
+	}
+	if let Some(op) = StackOp::from_word(word) {
+		return Ok(Token::Stack(op));
+	}
+	match word {
+		"if" => return Ok(Token::If),
+		"else" => return Ok(Token::Else),
+		"then" => return Ok(Token::Then),
+		_ => {}
+	}
+	if is_name(word) {
+		return Ok(Token::Var(word.to_string()));
+	}
+	if let Some(name) = word.strip_prefix("->") {
+		// not '->if' or '->dup', which could never be read back
+		if let Ok(Token::Var(name)) = parse_word(name) {
+			return Ok(Token::Store(name));
+		}
+	}
+	// an optional sign followed by at least one digit
+	let digits = word.strip_prefix('-').or_else(|| word.strip_prefix('+')).unwrap_or(word);
+	if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+		return Err(LexErrorKind::UnknownToken(word.to_string()));
+	}
+	word.parse().map(Token::Operand).map_err(|_| LexErrorKind::OutOfRange(word.to_string()))
+}
+
+// Returns whether 'word' can name a variable: a letter or '_' followed by letters, digits or '_'.
+fn is_name(word: &str) -> bool {
+	let mut chars = word.chars();
+	match chars.next() {
+		Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || c == '_'),
+		_ => false,
+	}
+}
+
+// Splits 'input' on whitespace, pairing each word with its 1-based starting column.
+fn words(input: &str) -> Vec<(usize, &str)> {
+	let mut out = Vec::new();
+	let mut start = None;
+	for (column, (i, c)) in input.char_indices().enumerate() {
+		match (c.is_whitespace(), start) {
+			(false, None) => start = Some((column + 1, i)),
+			(true, Some((col, begin))) => {
+				out.push((col, &input[begin..i]));
+				start = None;
+			}
+			_ => {}
+		}
+	}
+	if let Some((col, begin)) = start {
+		out.push((col, &input[begin..]));
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use eval;
+
+	#[test]
+	fn parses_negative_literals() {
+		let tokens = parse_postfix("-1 2 3 + *").unwrap();
+		assert_eq!(tokens, vec![
+			Token::Operand(-1),
+			Token::Operand(2),
+			Token::Operand(3),
+			Token::Operator(Operator::Add),
+			Token::Operator(Operator::Mul),
+		]);
+		assert_eq!(Some(-5), eval(&tokens));
+
+		// a '-' on its own is subtraction, whatever whitespace surrounds it
+		assert_eq!(Some(-3), eval(&parse_postfix("\t2  5 -\n").unwrap()));
+	}
+
+	#[test]
+	fn reports_columns() {
+		assert_eq!(Err(LexError { column: 5, kind: LexErrorKind::UnknownToken("#".to_string()) }),
+			parse_postfix("1 2 # +"));
+		assert_eq!(Err(LexError { column: 3, kind: LexErrorKind::UnknownToken("->2".to_string()) }),
+			parse_postfix("1 ->2"));
+		assert_eq!(Err(LexError { column: 3, kind: LexErrorKind::UnknownToken("->then".to_string()) }),
+			parse_postfix("1 ->then"));
+		assert_eq!(Err(LexError { column: 3, kind: LexErrorKind::UnknownToken("--1".to_string()) }),
+			parse_postfix("1 --1 +"));
+		assert_eq!(Err(LexError { column: 1, kind: LexErrorKind::OutOfRange("99999999999999999999".to_string()) }),
+			parse_postfix("99999999999999999999"));
+	}
+
+	#[test]
+	fn display_round_trips() {
+		let tokens = parse_postfix("-1 2 3 + * 4 - dup pick(1) rot ->x_1 x_1").unwrap();
+		assert_eq!(Token::Stack(StackOp::Pick(1)), tokens[8]);
+		assert_eq!(Token::Store("x_1".to_string()), tokens[10]);
+		assert_eq!(Token::Var("x_1".to_string()), tokens[11]);
+		let text = tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" ");
+		assert_eq!("-1 2 3 + * 4 - dup pick(1) rot ->x_1 x_1", text);
+		assert_eq!(tokens, parse_postfix(&text).unwrap());
+
+		let text = "x 0 <= y 1 <> and if 1 else x not then";
+		let tokens = parse_postfix(text).unwrap();
+		assert_eq!(Token::Operator(Operator::Le), tokens[2]);
+		assert_eq!(vec![Token::If, Token::Else, Token::Then], vec![tokens[7].clone(), tokens[9].clone(), tokens[12].clone()]);
+		assert_eq!(text, tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" "));
+	}
+
+	#[test]
+	fn defines_words() {
+		let mut words = Dictionary::new();
+		let tokens = parse_program(": square dup * ;\n: cube dup square * ; 3 square x cube", &mut words).unwrap();
+		assert_eq!(tokens, vec![
+			Token::Operand(3),
+			Token::Call("square".to_string()),
+			Token::Var("x".to_string()),
+			Token::Call("cube".to_string()),
+		]);
+		assert_eq!(Some(&[Token::Stack(StackOp::Dup), Token::Call("square".to_string()), Token::Operator(Operator::Mul)][..]),
+			words.get("cube"));
+
+		// later programs can use the words, but not define them again
+		assert_eq!(vec![Token::Call("cube".to_string())], parse_program("cube", &mut words).unwrap());
+		assert_eq!(Err(LexError { column: 3, kind: LexErrorKind::Redefined("square".to_string()) }),
+			parse_program(": square 2 * ;", &mut words));
+	}
+
+	#[test]
+	fn bad_definitions() {
+		let mut words = Dictionary::new();
+		let err = |column, kind| Err(LexError { column, kind });
+		assert_eq!(err(3, LexErrorKind::InvalidWordName("dup".to_string())), parse_program(": dup 2 ;", &mut words));
+		assert_eq!(err(3, LexErrorKind::InvalidWordName("2".to_string())), parse_program(": 2 2 ;", &mut words));
+		assert_eq!(err(7, LexErrorKind::NestedDefinition), parse_program(": a 1 : b 2 ; ;", &mut words));
+		assert_eq!(err(3, LexErrorKind::UnterminatedDefinition), parse_program("1 : a 1 2", &mut words));
+		assert_eq!(err(1, LexErrorKind::UnterminatedDefinition), parse_program(":", &mut words));
+		assert_eq!(err(3, LexErrorKind::UnmatchedSemicolon), parse_program("1 ;", &mut words));
+		assert!(!words.contains("a"));
+	}
+}
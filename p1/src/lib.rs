@@ -1,3 +1,49 @@
+extern crate hw2ex;
+extern crate num_bigint;
+extern crate num_integer;
+extern crate num_rational;
+extern crate num_traits;
+extern crate p3;
+#[cfg(test)]
+extern crate proptest;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+use std::{error, fmt};
+use std::collections::HashMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub use num_bigint::BigInt;
+pub use compile::CompiledProgram;
+pub use expr::Expr;
+pub use infix::{to_infix, to_infix_tokens};
+pub use lexer::{parse_postfix, parse_program, LexError, LexErrorKind};
+pub use machine::{eval_traced, Machine, Trace, TraceStep};
+pub use number::Number;
+pub use optimize::optimize;
+pub use stream::{eval_iter, Evaluator};
+pub use words::{Dictionary, DEFAULT_MAX_DEPTH};
+
+mod compile;
+mod expr;
+mod infix;
+mod lexer;
+mod machine;
+mod number;
+mod optimize;
+#[cfg(test)]
+mod properties;
+#[cfg(feature = "serde")]
+mod serialize;
+mod stream;
+mod symbolic;
+mod words;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Operator {
 	// '+'
 	Add,
@@ -5,74 +51,566 @@ pub enum Operator {
 	Sub,
 	// '*'
 	Mul,
+	// '/', rounds toward zero
+	Div,
+	// '%', takes the sign of the dividend
+	Rem,
+	// '^'
+	Pow,
+	// 'neg', unary minus
+	Neg,
+	// 'abs'
+	Abs,
+	// 'min'
+	Min,
+	// 'max'
+	Max,
+	// '=', and the other comparisons below give 1 when true and 0 when false
+	Eq,
+	// '<>'
+	Ne,
+	// '<'
+	Lt,
+	// '<='
+	Le,
+	// '>'
+	Gt,
+	// '>='
+	Ge,
+	// 'and': 1 if both values are non-zero
+	And,
+	// 'or': 1 if either value is non-zero
+	Or,
+	// 'not': 1 if the value is zero
+	Not,
+}
+
+// Forth-style words that rearrange the stack without doing arithmetic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackOp {
+	// 'dup': a -- a a
+	Dup,
+	// 'drop': a --
+	Drop,
+	// 'swap': a b -- b a
+	Swap,
+	// 'over': a b -- a b a
+	Over,
+	// 'rot': a b c -- b c a
+	Rot,
+	// 'pick(n)': copies the value n below the top, so 'pick(0)' is 'dup'
+	Pick(usize),
+	// 'clear': removes every value
+	Clear,
 }
+
+/// One element of a postfix program. Operands are 'isize' unless another 'Number' type
+/// is given.
+///
+/// With the 'serde' feature, tokens serialize to JSON as follows, and read back the same
+/// way:
+///
+/// | Token | JSON |
+/// |---|---|
+/// | 'Operator(Operator::Add)' | '{"operator": "+"}', using the symbol 'parse_postfix' reads |
+/// | 'Operand(3)' | '{"operand": 3}', using the number type's own representation |
+/// | 'Stack(StackOp::Pick(2))' | '{"stack": "pick(2)"}', using the word 'parse_postfix' reads |
+/// | 'Var("x")', 'Store("x")', 'Call("f")' | '{"var": "x"}', '{"store": "x"}', '{"call": "f"}' |
+/// | 'If', 'Else', 'Then' | '"if"', '"else"', '"then"' |
+///
+/// 'Operator' and 'StackOp' on their own serialize to the bare strings above.
 // access a slice like: tokens[0]
-pub enum Token {
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum Token<T = isize> {
 	Operator(Operator),
-	Operand(isize),
+	Operand(T),
+	Stack(StackOp),
+	// pushes the value bound to the name in the 'Env'
+	Var(String),
+	// pops the top value and binds it to the name in the 'Env'
+	Store(String),
+	// runs the user word with the name from the 'Dictionary'
+	Call(String),
+	// 'if': pops a value and runs the tokens up to the matching 'else' or 'then' if it
+	// is non-zero, and the tokens after the 'else' otherwise
+	If,
+	// 'else'
+	Else,
+	// 'then': ends the conditional
+	Then,
+}
+
+/// Variable bindings for 'eval_with_env'.
+pub type Env<T = isize> = HashMap<String, T>;
+
+impl Operator {
+	/// Returns the number of operands the operator pops off the stack.
+	pub fn arity(&self) -> usize {
+		match *self {
+			Operator::Neg | Operator::Abs | Operator::Not => 1,
+			_ => 2,
+		}
+	}
+
+	/// Returns the symbol used for the operator in postfix text.
+	pub fn symbol(&self) -> &'static str {
+		match *self {
+			Operator::Add => "+",
+			Operator::Sub => "-",
+			Operator::Mul => "*",
+			Operator::Div => "/",
+			Operator::Rem => "%",
+			Operator::Pow => "^",
+			Operator::Neg => "neg",
+			Operator::Abs => "abs",
+			Operator::Min => "min",
+			Operator::Max => "max",
+			Operator::Eq => "=",
+			Operator::Ne => "<>",
+			Operator::Lt => "<",
+			Operator::Le => "<=",
+			Operator::Gt => ">",
+			Operator::Ge => ">=",
+			Operator::And => "and",
+			Operator::Or => "or",
+			Operator::Not => "not",
+		}
+	}
+
+	/// Returns the operator written as 'symbol' in postfix text, if there is one.
+	pub fn from_symbol(symbol: &str) -> Option<Operator> {
+		let op = match symbol {
+			"+" => Operator::Add,
+			"-" => Operator::Sub,
+			"*" => Operator::Mul,
+			"/" => Operator::Div,
+			"%" => Operator::Rem,
+			"^" => Operator::Pow,
+			"neg" => Operator::Neg,
+			"abs" => Operator::Abs,
+			"min" => Operator::Min,
+			"max" => Operator::Max,
+			"=" => Operator::Eq,
+			"<>" => Operator::Ne,
+			"<" => Operator::Lt,
+			"<=" => Operator::Le,
+			">" => Operator::Gt,
+			">=" => Operator::Ge,
+			"and" => Operator::And,
+			"or" => Operator::Or,
+			"not" => Operator::Not,
+			_ => return None,
+		};
+		Some(op)
+	}
+}
+
+impl fmt::Display for Operator {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.symbol())
+	}
+}
+
+impl StackOp {
+	/// Returns the number of values that must be on the stack for the word to run.
+	pub fn depth(&self) -> usize {
+		match *self {
+			StackOp::Clear => 0,
+			StackOp::Dup | StackOp::Drop => 1,
+			StackOp::Swap | StackOp::Over => 2,
+			StackOp::Rot => 3,
+			StackOp::Pick(n) => n.saturating_add(1),
+		}
+	}
+
+	/// Returns the word written as 'word' in postfix text, if there is one.
+	pub fn from_word(word: &str) -> Option<StackOp> {
+		let op = match word {
+			"dup" => StackOp::Dup,
+			"drop" => StackOp::Drop,
+			"swap" => StackOp::Swap,
+			"over" => StackOp::Over,
+			"rot" => StackOp::Rot,
+			"clear" => StackOp::Clear,
+			_ => {
+				// pick(n)
+				let n = word.strip_prefix("pick(")?.strip_suffix(')')?;
+				if !n.chars().all(|c| c.is_ascii_digit()) {
+					return None;
+				}
+				StackOp::Pick(n.parse().ok()?)
+			}
+		};
+		Some(op)
+	}
+
+	// Runs the word on the stack 'v', which must hold at least 'depth()' values.
+	fn run<T: Clone>(&self, v: &mut Vec<T>) {
+		let top = v.len();
+		match *self {
+			StackOp::Dup => v.push(v[top - 1].clone()),
+			StackOp::Drop => { v.pop(); }
+			StackOp::Swap => v.swap(top - 1, top - 2),
+			StackOp::Over => v.push(v[top - 2].clone()),
+			StackOp::Rot => v[top - 3..].rotate_left(1),
+			StackOp::Pick(n) => v.push(v[top - 1 - n].clone()),
+			StackOp::Clear => v.clear(),
+		}
+	}
+}
+
+impl fmt::Display for StackOp {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			StackOp::Dup => write!(f, "dup"),
+			StackOp::Drop => write!(f, "drop"),
+			StackOp::Swap => write!(f, "swap"),
+			StackOp::Over => write!(f, "over"),
+			StackOp::Rot => write!(f, "rot"),
+			StackOp::Pick(n) => write!(f, "pick({})", n),
+			StackOp::Clear => write!(f, "clear"),
+		}
+	}
+}
+
+/// Formats the token the way 'parse_postfix' reads it, so joining tokens with spaces
+/// round-trips.
+impl<T: fmt::Display> fmt::Display for Token<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Token::Operator(ref op) => write!(f, "{}", op),
+			Token::Operand(ref x) => write!(f, "{}", x),
+			Token::Stack(ref op) => write!(f, "{}", op),
+			Token::Var(ref name) => write!(f, "{}", name),
+			Token::Store(ref name) => write!(f, "->{}", name),
+			Token::Call(ref name) => write!(f, "{}", name),
+			Token::If => write!(f, "if"),
+			Token::Else => write!(f, "else"),
+			Token::Then => write!(f, "then"),
+		}
+	}
+}
+
+/// The reason a postfix expression failed to evaluate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+	/// An operator or stack word found fewer values on the stack than it needs.
+	Underflow,
+	/// More than one value was left on the stack at the end.
+	TrailingOperands,
+	/// The result of an operation does not fit in the number type.
+	Overflow,
+	/// The right-hand operand of '/' or '%' was zero.
+	DivideByZero,
+	/// The exponent of '^' was negative.
+	NegativeExponent,
+	/// The operator is not defined for the number type, e.g. 'min' on complex numbers.
+	Unsupported,
+	/// A variable was read before anything was bound to it.
+	UndefinedVariable,
+	/// A word was called that is not in the 'Dictionary'.
+	UndefinedWord,
+	/// Words called each other more deeply than 'Dictionary::max_depth' allows.
+	RecursionLimit,
+	/// An 'if' has no matching 'then', or an 'else' or 'then' has no matching 'if'.
+	UnmatchedConditional,
+	/// The two branches of a conditional leave different numbers of values on the stack.
+	/// Only 'CompiledProgram' checks this.
+	UnbalancedBranches,
+}
+
+/// An error from evaluating a postfix expression.
+///
+/// 'index' is the position in the token slice where evaluation failed. Errors that are
+/// only found once every token is consumed (e.g. 'TrailingOperands') use 'tokens.len()'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalError {
+	pub index: usize,
+	pub kind: ErrorKind,
 }
 
+impl fmt::Display for EvalError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let msg = match self.kind {
+			ErrorKind::Underflow => "stack underflow",
+			ErrorKind::TrailingOperands => "operands left on the stack",
+			ErrorKind::Overflow => "arithmetic overflow",
+			ErrorKind::DivideByZero => "division by zero",
+			ErrorKind::NegativeExponent => "negative exponent",
+			ErrorKind::Unsupported => "unsupported operator",
+			ErrorKind::UndefinedVariable => "undefined variable",
+			ErrorKind::UndefinedWord => "undefined word",
+			ErrorKind::RecursionLimit => "words nested too deeply",
+			ErrorKind::UnmatchedConditional => "unmatched if, else or then",
+			ErrorKind::UnbalancedBranches => "branches leave different stack depths",
+		};
+		write!(f, "{} at token {}", msg, self.index)
+	}
+}
 
+impl error::Error for EvalError {}
 
 /// Evaluates the postix expression
 ///
-/// Input: a postfix expression, where each element contains an operator or operand. 
+/// Input: a postfix expression, where each element contains an operator or operand.
 /// Returns: if postfix is valid: returns 'Some(value)';
-///		otherwise, returns 'None'.
-pub fn eval(tokens: &[Token]) -> Option<isize> {
-	// intialize a vector v for the stack 
-	let mut v = Vec::new();
-	// interate through the stack 
-	// tokens is a reference to Token
-	for i in tokens {
-		match *i {
-			// if the value is of isize type 
-			// v.push(x) is option type, 
-			// if it has something, option::some(value) 
-			Token::Operand(x) => v.push(x),
-			// if the value is a '+'
-			Token::Operator(Operator::Add) => {
-				// panics if the stack has less than 2 values 
-				// use 1 pattern for all cases of operators and then inside if then, check length then match again
-				if v.len() < 2 { 
-					return None;
-				} else {
-					let y = v.pop().unwrap(); 
-					let z = v.pop().unwrap();
-					v.push(y+z); 
-				}
+///     otherwise, returns 'None'.
+pub fn eval<T: Number>(tokens: &[Token<T>]) -> Option<T> {
+	try_eval(tokens).ok()
+}
+
+/// How integer operators behave when the result does not fit in the number type.
+///
+/// Every policy behaves the same in debug and release builds. Number types without a
+/// fixed width document how they treat the policy on their 'Number' impl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+	/// Fail with 'ErrorKind::Overflow'.
+	#[default]
+	Checked,
+	/// Wrap around at the boundary of the type.
+	Wrapping,
+	/// Clamp to the type's 'MIN' or 'MAX'.
+	Saturating,
+	/// Compute every intermediate value as a 'BigInt'. Only the final result has to fit
+	/// in the number type; use 'eval_big' to get results that do not.
+	Arbitrary,
+}
+
+/// Options for 'eval_with'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct EvalOptions {
+	pub overflow: OverflowPolicy,
+}
+
+/// Evaluates the postfix expression, reporting why it failed.
+///
+/// Input: a postfix expression, where each element contains an operator or operand.
+/// Returns: if postfix is valid: returns 'Ok(value)';
+///     otherwise, returns 'Err(EvalError)' with the index of the failing token.
+pub fn try_eval<T: Number>(tokens: &[Token<T>]) -> Result<T, EvalError> {
+	eval_with(tokens, &EvalOptions::default())
+}
+
+/// Evaluates the postfix expression using the overflow policy in 'options'.
+pub fn eval_with<T: Number>(tokens: &[Token<T>], options: &EvalOptions) -> Result<T, EvalError> {
+	eval_with_env(tokens, &mut Env::new(), options)
+}
+
+/// Evaluates the postfix expression, reading 'Var' tokens from 'env' and writing 'Store'
+/// tokens back to it, so one program can be run against many sets of bindings.
+///
+/// Reading a name that is not in 'env' fails with 'ErrorKind::UndefinedVariable'. When
+/// evaluation fails, 'env' may keep values stored before the failing token.
+pub fn eval_with_env<T: Number>(tokens: &[Token<T>], env: &mut Env<T>, options: &EvalOptions) -> Result<T, EvalError> {
+	eval_with_words(tokens, env, &Dictionary::new(), options)
+}
+
+/// Evaluates the postfix expression like 'eval_with_env', running 'Call' tokens from 'words'.
+///
+/// An error inside a word is reported at the index of the top-level token that called it.
+pub fn eval_with_words<T: Number>(tokens: &[Token<T>], env: &mut Env<T>, words: &Dictionary<T>, options: &EvalOptions) -> Result<T, EvalError> {
+	if options.overflow == OverflowPolicy::Arbitrary {
+		// promote the whole program if the number type converts to BigInt
+		if let (Ok(big), Some(mut big_env), Some(big_words)) = (to_big_tokens(tokens), to_big_env(env), to_big_words(words)) {
+			let overflow = EvalError { index: tokens.len(), kind: ErrorKind::Overflow };
+			let result = run(&big, &mut big_env, &big_words, options.overflow)?;
+			// the final result and every stored value still have to fit in the number type
+			let stored = big_env.into_iter()
+				.map(|(name, value)| T::from_big(&value).map(|x| (name, x)).ok_or(overflow))
+				.collect::<Result<Vec<_>, _>>()?;
+			env.extend(stored);
+			return T::from_big(&result).ok_or(overflow);
+		}
+	}
+	run(tokens, env, words, options.overflow)
+}
+
+/// Evaluates the postfix expression with arbitrary precision, so it never overflows.
+///
+/// Operands that cannot be converted to 'BigInt' (see 'Number::to_big') fail with
+/// 'ErrorKind::Unsupported'. To keep memory bounded, '^' still reports
+/// 'ErrorKind::Overflow' when the exponent is larger than 'MAX_BIG_EXPONENT' and the
+/// base is not 0, 1 or -1.
+pub fn eval_big<T: Number>(tokens: &[Token<T>]) -> Result<BigInt, EvalError> {
+	run(&to_big_tokens(tokens)?, &mut Env::new(), &Dictionary::new(), OverflowPolicy::Arbitrary)
+}
+
+/// The largest exponent 'eval_big' raises a number other than 0, 1 or -1 to.
+pub const MAX_BIG_EXPONENT: usize = 1 << 16;
+
+// Converts every operand to a BigInt.
+fn to_big_tokens<T: Number>(tokens: &[Token<T>]) -> Result<Vec<Token<BigInt>>, EvalError> {
+	tokens.iter().enumerate().map(|(index, t)| match *t {
+		Token::Operator(op) => Ok(Token::Operator(op)),
+		Token::Stack(op) => Ok(Token::Stack(op)),
+		Token::Var(ref name) => Ok(Token::Var(name.clone())),
+		Token::Store(ref name) => Ok(Token::Store(name.clone())),
+		Token::Call(ref name) => Ok(Token::Call(name.clone())),
+		Token::If => Ok(Token::If),
+		Token::Else => Ok(Token::Else),
+		Token::Then => Ok(Token::Then),
+		Token::Operand(ref x) => x.to_big().map(Token::Operand).ok_or(EvalError { index, kind: ErrorKind::Unsupported }),
+	}).collect()
+}
+
+// Converts every word body to BigInt operands, or returns 'None' if one cannot be.
+fn to_big_words<T: Number>(words: &Dictionary<T>) -> Option<Dictionary<BigInt>> {
+	let mut big = Dictionary::with_max_depth(words.max_depth());
+	for (name, body) in words.iter() {
+		big.define(name, to_big_tokens(body).ok()?);
+	}
+	Some(big)
+}
+
+// Converts every binding to a BigInt, or returns 'None' if one cannot be.
+fn to_big_env<T: Number>(env: &Env<T>) -> Option<Env<BigInt>> {
+	env.iter().map(|(name, x)| x.to_big().map(|big| (name.clone(), big))).collect()
+}
+
+/// Runs the postfix tokens on an existing stack, leaving whatever they produce on it.
+///
+/// Unlike 'eval_with_env' this does not require exactly one value at the end, so a
+/// calculator can keep its stack between lines. 'OverflowPolicy::Arbitrary' is applied
+/// to each operation, since the stack holds values of the number type itself. When a
+/// token fails, 'stack' and 'env' keep the changes made by the tokens before it.
+pub fn exec<T: Number>(tokens: &[Token<T>], stack: &mut Vec<T>, env: &mut Env<T>, options: &EvalOptions) -> Result<(), EvalError> {
+	exec_with_words(tokens, stack, env, &Dictionary::new(), options)
+}
+
+/// Runs the postfix tokens on an existing stack like 'exec', running 'Call' tokens from 'words'.
+pub fn exec_with_words<T: Number>(tokens: &[Token<T>], stack: &mut Vec<T>, env: &mut Env<T>, words: &Dictionary<T>, options: &EvalOptions) -> Result<(), EvalError> {
+	exec_tokens(tokens, stack, env, words, 0, options.overflow).map_err(|(index, kind)| EvalError { index, kind })
+}
+
+// Runs 'tokens' on the stack 'v', returning the index of the failing token with the
+// error. 'depth' is the number of word calls the tokens are nested in.
+fn exec_tokens<T: Number>(tokens: &[Token<T>], v: &mut Vec<T>, env: &mut Env<T>, words: &Dictionary<T>, depth: usize, policy: OverflowPolicy) -> Result<(), (usize, ErrorKind)> {
+	let mut pc = 0;
+	// the 'if' of each conditional being run, innermost last
+	let mut open = Vec::new();
+	while pc < tokens.len() {
+		pc = next(tokens, pc, &mut open, v, env, words, depth, policy).map_err(|kind| (pc, kind))?;
+	}
+	match open.pop() {
+		Some(index) => Err((index, ErrorKind::UnmatchedConditional)),
+		None => Ok(()),
+	}
+}
+
+// Runs the token at 'pc' and returns the index of the token to run after it. 'open'
+// holds the index of the 'if' of each conditional that will reach its 'then'.
+#[allow(clippy::too_many_arguments)]
+fn next<T: Number>(tokens: &[Token<T>], pc: usize, open: &mut Vec<usize>, v: &mut Vec<T>, env: &mut Env<T>, words: &Dictionary<T>, depth: usize, policy: OverflowPolicy) -> Result<usize, ErrorKind> {
+	match tokens[pc] {
+		Token::If => {
+			let end = matching(tokens, pc)?;
+			let c = v.pop().ok_or(ErrorKind::Underflow)?;
+			if c.is_true() {
+				open.push(pc);
+				Ok(pc + 1)
+			} else if let Token::Else = tokens[end] {
+				// the 'else' branch runs on to the 'then'
+				open.push(pc);
+				Ok(end + 1)
+			} else {
+				Ok(end + 1)
 			}
-			// if the value is a '-'
-			Token::Operator(Operator::Sub) => {
-				// panics if the stack has less than 2 values 
-				if v.len() < 2 {
-					return None;
-				} else {
-					let y = v.pop().unwrap();
-					let z = v.pop().unwrap();
-					v.push(z-y);
-				}
+		}
+		// the end of the 'if' branch, so skip the 'else' branch
+		Token::Else => {
+			open.pop().ok_or(ErrorKind::UnmatchedConditional)?;
+			Ok(matching(tokens, pc)? + 1)
+		}
+		Token::Then => {
+			open.pop().ok_or(ErrorKind::UnmatchedConditional)?;
+			Ok(pc + 1)
+		}
+		ref token => step(token, v, env, words, depth, policy).map(|()| pc + 1),
+	}
+}
+
+// Returns the index of the 'else' or 'then' that ends the branch starting at the 'if' or
+// 'else' at 'pc'.
+fn matching<T>(tokens: &[Token<T>], pc: usize) -> Result<usize, ErrorKind> {
+	let mut nested = 0;
+	for (i, token) in tokens.iter().enumerate().skip(pc + 1) {
+		match *token {
+			Token::If => nested += 1,
+			// an 'if' has at most one 'else'
+			Token::Else if nested == 0 => return match tokens[pc] {
+				Token::If => Ok(i),
+				_ => Err(ErrorKind::UnmatchedConditional),
+			},
+			Token::Then if nested == 0 => return Ok(i),
+			Token::Then => nested -= 1,
+			_ => {}
+		}
+	}
+	Err(ErrorKind::UnmatchedConditional)
+}
+
+// Runs a single token other than 'if', 'else' and 'then' on the stack 'v'. 'depth' is
+// the number of word calls it is nested in.
+fn step<T: Number>(token: &Token<T>, v: &mut Vec<T>, env: &mut Env<T>, words: &Dictionary<T>, depth: usize, policy: OverflowPolicy) -> Result<(), ErrorKind> {
+	match *token {
+		// if the value is a number, push it onto the stack
+		Token::Operand(ref x) => v.push(x.clone()),
+		Token::Operator(ref op) => {
+			// the operator needs 'arity' values on the stack
+			let n = op.arity();
+			if v.len() < n {
+				return Err(ErrorKind::Underflow);
 			}
-			// if the value is a '*'
-			Token::Operator(Operator::Mul) => {
-				// panics if the stack has less than 2 values 
-				if v.len() < 2 {
-					return None;
-				} else {
-					let y = v.pop().unwrap();
-					let z = v.pop().unwrap();
-					v.push(y*z);
-				}
+			let base = v.len() - n;
+			let x = T::apply(op, &v[base..], policy)?;
+			v.truncate(base);
+			v.push(x);
+		}
+		Token::Stack(ref op) => {
+			if v.len() < op.depth() {
+				return Err(ErrorKind::Underflow);
+			}
+			op.run(v);
+		}
+		Token::Var(ref name) => match env.get(name) {
+			Some(x) => v.push(x.clone()),
+			None => return Err(ErrorKind::UndefinedVariable),
+		},
+		Token::Store(ref name) => match v.pop() {
+			Some(x) => { env.insert(name.clone(), x); }
+			None => return Err(ErrorKind::Underflow),
+		},
+		Token::Call(ref name) => {
+			let body = words.get(name).ok_or(ErrorKind::UndefinedWord)?;
+			if depth >= words.max_depth() {
+				return Err(ErrorKind::RecursionLimit);
 			}
+			exec_tokens(body, v, env, words, depth + 1, policy).map_err(|(_, kind)| kind)?;
 		}
+		Token::If | Token::Else | Token::Then => unreachable!("conditionals are run by 'next'"),
+	}
+	Ok(())
+}
+
+// Runs the stack machine over 'tokens' on an empty stack.
+fn run<T: Number>(tokens: &[Token<T>], env: &mut Env<T>, words: &Dictionary<T>, policy: OverflowPolicy) -> Result<T, EvalError> {
+	// intialize a vector v for the stack
+	let mut v = Vec::new();
+	exec_with_words(tokens, &mut v, env, words, &EvalOptions { overflow: policy })?;
+	single_value(v, tokens.len())
+}
+
+// Returns the only value on the stack once all 'len' tokens have run.
+fn single_value<T>(mut v: Vec<T>, len: usize) -> Result<T, EvalError> {
+	// if there is only 1 value on the stack, return the value
+	// if there is more than 1 value on the stack, return an error
+	match v.len() {
+		0 => Err(EvalError { index: len, kind: ErrorKind::Underflow }),
+		1 => Ok(v.pop().unwrap()),
+		_ => Err(EvalError { index: len, kind: ErrorKind::TrailingOperands }),
 	}
-	// if there is only 1 value on the stack, return the value 
-	if v.len() == 1 {
-		return Some(v.pop().unwrap());
-	// if there is more than 1 value on the stack, return nothing
-	} else { 
-		return None;
-	} 
 }
 
 
@@ -80,7 +618,15 @@ pub fn eval(tokens: &[Token]) -> Option<isize> {
 mod tests {
 	use super::Operator;
 	use super::Token;
-	use super::eval;
+	use super::{eval, try_eval, eval_with, eval_big, EvalError, ErrorKind};
+	use super::{BigInt, Env, EvalOptions, OverflowPolicy, StackOp, eval_with_env};
+	use super::{CompiledProgram, Dictionary, eval_with_words, exec_with_words, optimize, to_infix};
+	use parse_program;
+	use parse_postfix;
+
+	fn run(text: &str, overflow: OverflowPolicy) -> Result<isize, ErrorKind> {
+		eval_with(&parse_postfix(text).unwrap(), &EvalOptions { overflow }).map_err(|e| e.kind)
+	}
     #[test]
     fn it_works() {
 
@@ -89,14 +635,266 @@ mod tests {
     	let v1 = Token::Operand(-1);
     	let v2 = Token::Operand(2);
     	let v3 = Token::Operand(3);
-    	// tokens 
-    	let tokens:[Token;5] = [v1, v2, v3, optr1, optr2 ]; 
+    	// tokens
+    	let tokens:[Token;5] = [v1, v2, v3, optr1, optr2 ];
 
-    	// calling eval to eval the 
+    	// calling eval to eval the
     	assert_eq!(Some(-5),eval(&tokens));
     }
 
+    #[test]
     fn neg_works() {
+    	// 1 + : not enough operands for '+'
+    	let tokens = [Token::Operand(1), Token::Operator(Operator::Add)];
+    	assert_eq!(None, eval(&tokens));
+    	assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::Underflow }), try_eval(&tokens));
+
+    	// 1 2 : two values left on the stack
+    	let tokens = [Token::Operand(1), Token::Operand(2)];
+    	assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::TrailingOperands }), try_eval(&tokens));
+
+    	// empty expression
+    	assert_eq!(Err(EvalError { index: 0, kind: ErrorKind::Underflow }), try_eval::<isize>(&[]));
+    }
+
+    #[test]
+    fn overflow() {
+    	let tokens = [Token::Operand(isize::MAX), Token::Operand(1), Token::Operator(Operator::Add)];
+    	assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::Overflow }), try_eval(&tokens));
+    	assert_eq!(None, eval(&tokens));
+    }
+
+    #[test]
+    fn overflow_policies() {
+    	// MAX 2 * 2 - : the product overflows, but the final result would not in Arbitrary mode
+    	let tokens = [Token::Operand(isize::MAX), Token::Operand(2), Token::Operator(Operator::Mul),
+    		Token::Operand(isize::MAX), Token::Operator(Operator::Sub)];
+    	let with = |overflow| eval_with(&tokens, &EvalOptions { overflow });
 
+    	assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::Overflow }), with(OverflowPolicy::Checked));
+    	assert_eq!(Ok(isize::MAX.wrapping_mul(2).wrapping_sub(isize::MAX)), with(OverflowPolicy::Wrapping));
+    	assert_eq!(Ok(0), with(OverflowPolicy::Saturating));
+    	assert_eq!(Ok(isize::MAX), with(OverflowPolicy::Arbitrary));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn arbitrary_precision() {
+    	let tokens = [Token::Operand(isize::MAX), Token::Operand(isize::MAX), Token::Operator(Operator::Mul)];
+    	let expected = BigInt::from(isize::MAX) * BigInt::from(isize::MAX);
+    	assert_eq!(Ok(expected), eval_big(&tokens));
+    	// the result is too big to hand back as an isize
+    	assert_eq!(Err(EvalError { index: 3, kind: ErrorKind::Overflow }),
+    		eval_with(&tokens, &EvalOptions { overflow: OverflowPolicy::Arbitrary }));
+    }
+
+    #[test]
+    fn division_and_powers() {
+    	let checked = OverflowPolicy::Checked;
+    	assert_eq!(Ok(-3), run("-7 2 /", checked));
+    	assert_eq!(Ok(-1), run("-7 2 %", checked));
+    	assert_eq!(Ok(1024), run("2 10 ^", checked));
+    	assert_eq!(Ok(1), run("0 0 ^", checked));
+    	assert_eq!(Err(ErrorKind::DivideByZero), run("1 0 /", checked));
+    	assert_eq!(Err(ErrorKind::DivideByZero), run("1 0 %", OverflowPolicy::Wrapping));
+    	assert_eq!(Err(ErrorKind::NegativeExponent), run("2 -1 ^", checked));
+    	assert_eq!(Err(ErrorKind::Overflow), run("2 64 ^ 1 -", checked));
+
+    	assert_eq!(Ok(0), run("2 64 ^", OverflowPolicy::Wrapping));
+    	assert_eq!(Ok(isize::MIN), run("-2 65 ^", OverflowPolicy::Saturating));
+    	assert_eq!(Ok(isize::MAX), run("-2 64 ^", OverflowPolicy::Saturating));
+    	assert_eq!(Ok(1 << 32), run("2 64 ^ 2 32 ^ /", OverflowPolicy::Arbitrary));
+    	assert_eq!(Ok(-1), run("-1 9999999999 ^", OverflowPolicy::Arbitrary));
+    	assert_eq!(Err(ErrorKind::Overflow), run("2 9999999999 ^ 0 *", OverflowPolicy::Arbitrary));
+    }
+
+    #[test]
+    fn unary_and_min_max() {
+    	let checked = OverflowPolicy::Checked;
+    	assert_eq!(Ok(-3), run("1 2 + neg", checked));
+    	assert_eq!(Ok(3), run("-3 abs", checked));
+    	assert_eq!(Ok(2), run("5 2 min", checked));
+    	assert_eq!(Ok(5), run("5 2 max", checked));
+    	assert_eq!(Err(ErrorKind::Underflow), run("neg", checked));
+    	assert_eq!(Err(ErrorKind::Underflow), run("1 max", checked));
+
+    	let min = isize::MIN.to_string();
+    	assert_eq!(Err(ErrorKind::Overflow), run(&format!("{} abs", min), checked));
+    	assert_eq!(Err(ErrorKind::Overflow), run(&format!("{} -1 /", min), checked));
+    	assert_eq!(Ok(isize::MIN), run(&format!("{} neg", min), OverflowPolicy::Wrapping));
+    	assert_eq!(Ok(isize::MAX), run(&format!("{} -1 /", min), OverflowPolicy::Saturating));
+    	assert_eq!(Ok(0), run(&format!("{} -1 %", min), OverflowPolicy::Saturating));
+    	assert_eq!(Ok(isize::MAX), run(&format!("{} neg 1 -", min), OverflowPolicy::Arbitrary));
+    }
+
+    #[test]
+    fn arity() {
+    	assert_eq!(1, Operator::Neg.arity());
+    	assert_eq!(1, Operator::Abs.arity());
+    	assert_eq!(2, Operator::Pow.arity());
+    	assert_eq!(Some(Operator::Max), Operator::from_symbol("max"));
+    	assert_eq!(None, Operator::from_symbol("sqrt"));
+    }
+
+    #[test]
+    fn stack_words() {
+    	let checked = OverflowPolicy::Checked;
+    	// dup: 3 3 *
+    	assert_eq!(Ok(9), run("3 dup *", checked));
+    	// drop: 1 2 drop -> 1
+    	assert_eq!(Ok(1), run("1 2 drop", checked));
+    	// swap: 1 2 swap - -> 2 - 1
+    	assert_eq!(Ok(1), run("1 2 swap -", checked));
+    	// over: 5 2 over -> 5 2 5
+    	assert_eq!(Ok(2), run("5 2 over - +", checked));
+    	// rot: 1 2 3 rot -> 2 3 1, read back as the digits of 231
+    	assert_eq!(Ok(231), run("1 2 3 rot swap 10 * + swap 100 * +", checked));
+    	// pick: 5 7 9 pick(2) -> 5 7 9 5
+    	assert_eq!(Ok(521), run("5 7 9 pick(2) 100 * + + +", checked));
+    	assert_eq!(Ok(4), run("4 pick(0) max", checked));
+    	// clear: only the 7 pushed afterwards is left
+    	assert_eq!(Ok(7), run("1 2 3 clear 7", checked));
+    }
+
+    #[test]
+    fn stack_word_underflow() {
+    	let checked = OverflowPolicy::Checked;
+    	assert_eq!(Err(ErrorKind::Underflow), run("dup", checked));
+    	assert_eq!(Err(ErrorKind::Underflow), run("drop", checked));
+    	assert_eq!(Err(ErrorKind::Underflow), run("1 swap", checked));
+    	assert_eq!(Err(ErrorKind::Underflow), run("1 over", checked));
+    	assert_eq!(Err(ErrorKind::Underflow), run("1 2 rot", checked));
+    	assert_eq!(Err(ErrorKind::Underflow), run("1 2 pick(2)", checked));
+    	assert_eq!(Err(ErrorKind::Underflow), run("1 2 clear", checked));
+    	assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::Underflow }),
+    		try_eval(&[Token::Operand(1), Token::Stack(StackOp::Pick(usize::MAX))]));
+    }
+
+    #[test]
+    fn variables() {
+    	let tokens = parse_postfix("x x * y +").unwrap();
+    	let options = EvalOptions::default();
+    	let mut env = Env::new();
+    	env.insert("y".to_string(), 1);
+    	// the same program against several bindings of x
+    	for x in 1..4 {
+    		env.insert("x".to_string(), x);
+    		assert_eq!(Ok(x * x + 1), eval_with_env(&tokens, &mut env, &options));
+    	}
+    	env.remove("y");
+    	assert_eq!(Err(EvalError { index: 3, kind: ErrorKind::UndefinedVariable }),
+    		eval_with_env(&tokens, &mut env, &options));
+    }
+
+    #[test]
+    fn store() {
+    	let options = EvalOptions::default();
+    	let mut env = Env::new();
+    	let tokens = parse_postfix("2 3 + ->t t t *").unwrap();
+    	assert_eq!(Ok(25), eval_with_env(&tokens, &mut env, &options));
+    	assert_eq!(Some(&5), env.get("t"));
+    	assert_eq!(Err(EvalError { index: 0, kind: ErrorKind::Underflow }),
+    		eval_with_env(&parse_postfix("->t").unwrap(), &mut env, &options));
+
+    	// stored values have to fit once arbitrary precision evaluation is done
+    	let options = EvalOptions { overflow: OverflowPolicy::Arbitrary };
+    	let tokens = parse_postfix("2 100 ^ ->big 1").unwrap();
+    	assert_eq!(Err(EvalError { index: 5, kind: ErrorKind::Overflow }), eval_with_env(&tokens, &mut env, &options));
+    	let tokens = parse_postfix("2 100 ^ ->big big 2 99 ^ /").unwrap();
+    	assert_eq!(Err(EvalError { index: 9, kind: ErrorKind::Overflow }), eval_with_env(&tokens, &mut env, &options));
+    	assert_eq!(Some(&5), env.get("t"));
+    	let tokens = parse_postfix("2 100 ^ ->big big 2 99 ^ / ->big big").unwrap();
+    	assert_eq!(Ok(2), eval_with_env(&tokens, &mut env, &options));
+    	assert_eq!(Some(&2), env.get("big"));
+    }
+
+    #[test]
+    fn user_words() {
+    	let options = EvalOptions::default();
+    	let mut words = Dictionary::new();
+    	let tokens = parse_program(": square dup * ; : hyp2 square swap square + ; 3 4 hyp2", &mut words).unwrap();
+    	assert_eq!(Ok(25), eval_with_words(&tokens, &mut Env::new(), &words, &options));
+
+    	// without the dictionary the call cannot run
+    	assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::UndefinedWord }), eval_with_env(&tokens, &mut Env::new(), &options));
+
+    	// errors inside a word point at the call
+    	let tokens = parse_program("4 square square x", &mut words).unwrap();
+    	assert_eq!(Err(EvalError { index: 3, kind: ErrorKind::UndefinedVariable }),
+    		eval_with_words(&tokens, &mut Env::new(), &words, &options));
+    	let tokens = parse_program("square", &mut words).unwrap();
+    	assert_eq!(Err(EvalError { index: 0, kind: ErrorKind::Underflow }),
+    		eval_with_words(&tokens, &mut Env::new(), &words, &options));
+
+    	// names that are not words stay variables, so programs with inputs still compile,
+    	// print and optimize
+    	let tokens = parse_program("x 1 + 0 +", &mut words).unwrap();
+    	assert_eq!(Token::Var("x".to_string()), tokens[0]);
+    	let mut env = Env::new();
+    	env.insert("x".to_string(), 2);
+    	assert_eq!(Ok(3), CompiledProgram::with_words(&tokens, &words).unwrap().eval(&mut env, &options));
+    	assert_eq!(Ok("x + 1 + 0".to_string()), to_infix(&tokens));
+    	assert_eq!(parse_postfix("x 1 +").unwrap(), optimize(&tokens));
+
+    	// a misspelled word is a variable, which 'unknown_names' can tell apart from inputs
+    	let tokens = parse_program(": cube dup sqare * ; 2 ->y x y cube sqare", &mut words).unwrap();
+    	assert_eq!(Err(EvalError { index: 4, kind: ErrorKind::UndefinedVariable }), eval_with_words(&tokens, &mut env, &words, &options));
+    	assert_eq!(vec![(4, "sqare".to_string()), (5, "sqare".to_string())], words.unknown_names(&tokens, &env));
+    	assert_eq!(vec![(2, "x".to_string()), (4, "sqare".to_string()), (5, "sqare".to_string())],
+    		words.unknown_names(&tokens, &Env::new()));
+    }
+
+    #[test]
+    fn recursion_limit() {
+    	let options = EvalOptions::default();
+    	let mut words = Dictionary::with_max_depth(3);
+    	let tokens = parse_program(": forever 1 + forever ; 0 forever", &mut words).unwrap();
+    	assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::RecursionLimit }),
+    		eval_with_words(&tokens, &mut Env::new(), &words, &options));
+
+    	// three nested calls are allowed, a fourth is not
+    	let mut words = Dictionary::with_max_depth(3);
+    	let tokens = parse_program(": a 1 ; : b a ; : c b ; : d c ; c d", &mut words).unwrap();
+    	let mut stack = Vec::new();
+    	assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::RecursionLimit }),
+    		exec_with_words(&tokens, &mut stack, &mut Env::new(), &words, &options));
+    	assert_eq!(vec![1], stack);
+    }
+
+    #[test]
+    fn comparisons() {
+    	let run = |text| try_eval(&parse_program(text, &mut Dictionary::new()).unwrap());
+    	assert_eq!(Ok(1), run("2 3 <"));
+    	assert_eq!(Ok(0), run("2 3 >="));
+    	assert_eq!(Ok(1), run("4 4 = 4 5 <> and"));
+    	assert_eq!(Ok(1), run("0 7 or"));
+    	assert_eq!(Ok(0), run("-2 not"));
+    	assert_eq!(Some(1.0), eval(&[Token::Operand(0.5), Token::Operand(0.25), Token::Operator(Operator::Gt)]));
+    }
+
+    #[test]
+    fn conditionals() {
+    	let options = EvalOptions::default();
+    	let mut words = Dictionary::new();
+    	let price = parse_program("qty 10 >= if qty 9 * else qty 10 * then", &mut words).unwrap();
+    	for &(qty, total) in &[(3, 30), (10, 90), (12, 108)] {
+    		let mut env = Env::new();
+    		env.insert("qty".to_string(), qty);
+    		assert_eq!(Ok(total), eval_with_env(&price, &mut env, &options));
+    	}
+    	// the branch not taken is skipped, errors and all
+    	assert_eq!(Ok(5), try_eval(&parse_program("0 if 1 0 / then 5", &mut words).unwrap()));
+    	assert_eq!(Ok(2), try_eval(&parse_program("1 if 0 if 1 else 2 then else 3 then", &mut words).unwrap()));
+
+    	let tokens = parse_program(": fact dup 1 > if dup 1 - fact * then ; 5 fact", &mut words).unwrap();
+    	assert_eq!(Ok(120), eval_with_words(&tokens, &mut Env::new(), &words, &options));
+    }
+
+    #[test]
+    fn unmatched_conditionals() {
+    	let run = |text| try_eval(&parse_program(text, &mut Dictionary::new()).unwrap()).map_err(|e| (e.index, e.kind));
+    	assert_eq!(Err((1, ErrorKind::UnmatchedConditional)), run("1 if 2"));
+    	assert_eq!(Err((1, ErrorKind::UnmatchedConditional)), run("1 then"));
+    	assert_eq!(Err((3, ErrorKind::UnmatchedConditional)), run("1 if 2 else 3 else 4 then"));
+    	assert_eq!(Err((0, ErrorKind::Underflow)), run("if 1 then"));
+    }
+}
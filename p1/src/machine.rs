@@ -0,0 +1,249 @@
+use std::fmt;
+
+use {next, single_value, Dictionary, Env, ErrorKind, EvalError, EvalOptions, Number, Token};
+
+/// What one token did to the stack.
+///
+/// Tokens skipped by a conditional do not get a step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceStep<T> {
+	/// Position of the token in the program.
+	pub index: usize,
+	pub token: Token<T>,
+	/// The stack before the token ran, bottom first.
+	pub before: Vec<T>,
+	/// The stack after the token ran, bottom first.
+	pub after: Vec<T>,
+}
+
+/// A full evaluation trace from 'eval_traced'.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace<T> {
+	/// One step per token that ran successfully.
+	pub steps: Vec<TraceStep<T>>,
+	/// The result 'eval_with_env' would have returned.
+	pub result: Result<T, EvalError>,
+}
+
+/// Evaluates the postfix expression, recording the stack before and after every token.
+///
+/// When a token fails, the trace stops before it and 'result' holds the error.
+pub fn eval_traced<T: Number>(tokens: &[Token<T>], env: Env<T>, options: &EvalOptions) -> Trace<T> {
+	let mut machine = Machine::with_env(tokens, env, options);
+	let mut steps = Vec::new();
+	for s in &mut machine {
+		match s {
+			Ok(s) => steps.push(s),
+			Err(e) => return Trace { steps, result: Err(e) },
+		}
+	}
+	Trace { steps, result: machine.finish() }
+}
+
+/// A postfix machine that runs a program one token at a time.
+///
+/// Iterating yields a 'TraceStep' per token, or the error of the token that failed, after
+/// which iteration stops. 'OverflowPolicy::Arbitrary' is applied to each operation, as
+/// with 'exec'.
+pub struct Machine<'a, T: 'a> {
+	tokens: &'a [Token<T>],
+	// index of the next token to run
+	pos: usize,
+	stack: Vec<T>,
+	env: Env<T>,
+	words: Dictionary<T>,
+	// the 'if' of each conditional being run, innermost last
+	open: Vec<usize>,
+	options: EvalOptions,
+	error: Option<EvalError>,
+}
+
+impl<'a, T: Number> Machine<'a, T> {
+	/// Creates a machine at the start of 'tokens' with an empty stack and no variables.
+	pub fn new(tokens: &'a [Token<T>], options: &EvalOptions) -> Machine<'a, T> {
+		Machine::with_env(tokens, Env::new(), options)
+	}
+
+	/// Creates a machine at the start of 'tokens' that reads and stores variables in 'env'.
+	pub fn with_env(tokens: &'a [Token<T>], env: Env<T>, options: &EvalOptions) -> Machine<'a, T> {
+		Machine { tokens, pos: 0, stack: Vec::new(), env, words: Dictionary::new(), open: Vec::new(), options: *options, error: None }
+	}
+
+	/// Makes the machine run 'Call' tokens from 'words'. A call runs as a single step.
+	pub fn with_words(mut self, words: Dictionary<T>) -> Machine<'a, T> {
+		self.words = words;
+		self
+	}
+
+	/// Runs the next token. Returns 'None' once every token has run or one has failed.
+	pub fn step(&mut self) -> Option<Result<TraceStep<T>, EvalError>> {
+		if self.is_done() {
+			return None;
+		}
+		let index = self.pos;
+		let token = &self.tokens[index];
+		let before = self.stack.clone();
+		match next(self.tokens, index, &mut self.open, &mut self.stack, &mut self.env, &self.words, 0, self.options.overflow) {
+			Ok(pos) => {
+				self.pos = pos;
+				Some(Ok(TraceStep { index, token: token.clone(), before, after: self.stack.clone() }))
+			}
+			Err(kind) => {
+				// leave the stack as it was before the failing token, which may have been
+				// part way through a word
+				self.stack = before;
+				let e = EvalError { index, kind };
+				self.error = Some(e);
+				Some(Err(e))
+			}
+		}
+	}
+
+	/// Runs the remaining tokens and returns the single value left on the stack.
+	pub fn finish(mut self) -> Result<T, EvalError> {
+		while let Some(s) = self.step() {
+			s?;
+		}
+		if let Some(e) = self.error {
+			return Err(e);
+		}
+		if let Some(&index) = self.open.last() {
+			return Err(EvalError { index, kind: ErrorKind::UnmatchedConditional });
+		}
+		single_value(self.stack, self.tokens.len())
+	}
+
+	/// Returns whether every token has run or one has failed.
+	pub fn is_done(&self) -> bool {
+		self.error.is_some() || self.pos == self.tokens.len()
+	}
+
+	/// Returns the index of the next token to run, which is after any tokens a
+	/// conditional skipped.
+	pub fn position(&self) -> usize {
+		self.pos
+	}
+
+	/// Returns the current stack, bottom first.
+	pub fn stack(&self) -> &[T] {
+		&self.stack
+	}
+
+	/// Returns the current variable bindings.
+	pub fn env(&self) -> &Env<T> {
+		&self.env
+	}
+}
+
+impl<'a, T: Number> Iterator for Machine<'a, T> {
+	type Item = Result<TraceStep<T>, EvalError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.step()
+	}
+}
+
+// Formats a stack as "[1 2 3]".
+fn write_stack<T: fmt::Display>(f: &mut fmt::Formatter, stack: &[T]) -> fmt::Result {
+	write!(f, "[")?;
+	for (i, x) in stack.iter().enumerate() {
+		if i > 0 {
+			write!(f, " ")?;
+		}
+		write!(f, "{}", x)?;
+	}
+	write!(f, "]")
+}
+
+/// Formats the step as "index  token  before -> after", e.g. "2  +  [1 2] -> [3]".
+impl<T: fmt::Display> fmt::Display for TraceStep<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}  {}  ", self.index, self.token)?;
+		write_stack(f, &self.before)?;
+		write!(f, " -> ")?;
+		write_stack(f, &self.after)
+	}
+}
+
+/// Formats one step per line, followed by the result or the error.
+impl<T: fmt::Display> fmt::Display for Trace<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for s in &self.steps {
+			writeln!(f, "{}", s)?;
+		}
+		match self.result {
+			Ok(ref x) => write!(f, "= {}", x),
+			Err(ref e) => write!(f, "error: {}", e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use parse_postfix;
+
+	#[test]
+	fn traces_every_token() {
+		let tokens = parse_postfix("1 2 + 3 *").unwrap();
+		let trace = eval_traced(&tokens, Env::new(), &EvalOptions::default());
+		assert_eq!(Ok(9), trace.result);
+		assert_eq!(5, trace.steps.len());
+		assert_eq!(TraceStep { index: 2, token: tokens[2].clone(), before: vec![1, 2], after: vec![3] }, trace.steps[2]);
+		assert_eq!("0  1  [] -> [1]\n1  2  [1] -> [1 2]\n2  +  [1 2] -> [3]\n3  3  [3] -> [3 3]\n4  *  [3 3] -> [9]\n= 9",
+			trace.to_string());
+	}
+
+	#[test]
+	fn trace_stops_at_error() {
+		let tokens = parse_postfix("1 2 + + 5").unwrap();
+		let trace = eval_traced(&tokens, Env::new(), &EvalOptions::default());
+		assert_eq!(3, trace.steps.len());
+		assert_eq!(Err(EvalError { index: 3, kind: ErrorKind::Underflow }), trace.result);
+		assert!(trace.to_string().ends_with("error: stack underflow at token 3"));
+
+		// leftover values are only found at the end
+		let tokens = parse_postfix("1 2").unwrap();
+		let trace = eval_traced(&tokens, Env::new(), &EvalOptions::default());
+		assert_eq!(2, trace.steps.len());
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::TrailingOperands }), trace.result);
+	}
+
+	#[test]
+	fn steps_one_token_at_a_time() {
+		let tokens = parse_postfix("4 ->x x x * 1 /").unwrap();
+		let mut m = Machine::new(&tokens, &EvalOptions::default());
+		m.step().unwrap().unwrap();
+		m.step().unwrap().unwrap();
+		assert_eq!(Some(&4), m.env().get("x"));
+		assert_eq!(&[] as &[isize], m.stack());
+		m.step().unwrap().unwrap();
+		m.step().unwrap().unwrap();
+		assert_eq!(&[4, 4], m.stack());
+		assert_eq!(4, m.position());
+		assert!(!m.is_done());
+		assert_eq!(Ok(16), m.finish());
+
+		let tokens = parse_postfix("1 0 / 2").unwrap();
+		let mut m = Machine::new(&tokens, &EvalOptions::default());
+		assert_eq!(2, m.by_ref().take_while(|s| s.is_ok()).count());
+		assert!(m.is_done());
+		assert_eq!(None, m.next());
+		// the failing token is not applied
+		assert_eq!(&[1, 0], m.stack());
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::DivideByZero }), m.finish());
+	}
+
+	#[test]
+	fn skips_untaken_branches() {
+		let tokens = parse_postfix("0 if 1 else 2 then 3 +").unwrap();
+		let trace = eval_traced(&tokens, Env::new(), &EvalOptions::default());
+		assert_eq!(Ok(5), trace.result);
+		let ran: Vec<usize> = trace.steps.iter().map(|s| s.index).collect();
+		assert_eq!(vec![0, 1, 4, 5, 6, 7], ran);
+
+		let tokens = parse_postfix("1 if 2").unwrap();
+		let trace = eval_traced(&tokens, Env::new(), &EvalOptions::default());
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::UnmatchedConditional }), trace.result);
+	}
+}
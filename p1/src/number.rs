@@ -0,0 +1,379 @@
+use std::convert::TryFrom;
+use std::ops;
+
+use hw2ex::Complex;
+use num_integer::Integer;
+use num_rational::Ratio;
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, One, Signed, ToPrimitive, Zero};
+
+use {BigInt, ErrorKind, Operator, OverflowPolicy, MAX_BIG_EXPONENT};
+
+/// A number type the postfix evaluator can compute with.
+///
+/// Implemented for the signed integers, 'f32', 'f64', 'BigInt', rationals ('Ratio<T>') and
+/// 'hw2ex::Complex<T>'. Other types only have to provide the 'ops' traits below to support
+/// '+', '-' and '*'.
+pub trait Number: Clone + PartialEq + ops::Add<Output = Self> + ops::Sub<Output = Self> + ops::Mul<Output = Self> {
+	/// Applies 'op' to 'args', which hold 'op.arity()' values in stack order (bottom first).
+	///
+	/// The default handles '+', '-' and '*' with the 'ops' traits, ignoring 'policy', and
+	/// reports every other operator as 'ErrorKind::Unsupported'.
+	fn apply(op: &Operator, args: &[Self], policy: OverflowPolicy) -> Result<Self, ErrorKind> {
+		let _ = policy;
+		let (z, y) = (args[0].clone(), args[args.len() - 1].clone());
+		match *op {
+			Operator::Add => Ok(z + y),
+			Operator::Sub => Ok(z - y),
+			Operator::Mul => Ok(z * y),
+			_ => Err(ErrorKind::Unsupported),
+		}
+	}
+
+	/// Returns whether the value counts as true for 'if': anything but zero.
+	fn is_true(&self) -> bool {
+		// only zero is equal to itself minus itself; NaN is not equal to anything
+		self.clone() - self.clone() != *self
+	}
+
+	/// Converts the value to a 'BigInt' for 'OverflowPolicy::Arbitrary'.
+	///
+	/// Returns 'None' for types that are not integers; those apply 'Arbitrary' per operation.
+	fn to_big(&self) -> Option<BigInt> {
+		None
+	}
+
+	/// Converts a 'BigInt' back, or returns 'None' if it does not fit.
+	fn from_big(big: &BigInt) -> Option<Self> {
+		let _ = big;
+		None
+	}
+}
+
+// Signed integers honour every overflow policy.
+macro_rules! signed_number {
+	($($t:ident)*) => {$(
+		impl Number for $t {
+			fn apply(op: &Operator, args: &[$t], policy: OverflowPolicy) -> Result<$t, ErrorKind> {
+				// 'z' is the lower operand and 'y' the top of the stack; unary operators only use 'y'
+				let (z, y) = (args[0], args[args.len() - 1]);
+				if let Some(b) = logic(op, &z, &y, &0) {
+					return Ok(b as $t);
+				}
+				check_domain(op, y == 0, y < 0)?;
+				let result = match policy {
+					// a single operation that does not fit is an overflow with arbitrary
+					// precision too; 'eval_with' promotes whole programs to 'BigInt' instead
+					OverflowPolicy::Checked | OverflowPolicy::Arbitrary => match *op {
+						Operator::Add => z.checked_add(y),
+						Operator::Sub => z.checked_sub(y),
+						Operator::Mul => z.checked_mul(y),
+						Operator::Div => z.checked_div(y),
+						Operator::Rem => z.checked_rem(y),
+						Operator::Pow => pow_by_squaring(z, y as u128, 1, |a, b| a.checked_mul(b)),
+						Operator::Neg => y.checked_neg(),
+						Operator::Abs => y.checked_abs(),
+						Operator::Min => Some(z.min(y)),
+						Operator::Max => Some(z.max(y)),
+						_ => unreachable!("handled by 'logic'"),
+					},
+					OverflowPolicy::Wrapping => Some(match *op {
+						Operator::Add => z.wrapping_add(y),
+						Operator::Sub => z.wrapping_sub(y),
+						Operator::Mul => z.wrapping_mul(y),
+						Operator::Div => z.wrapping_div(y),
+						Operator::Rem => z.wrapping_rem(y),
+						Operator::Pow => pow_by_squaring(z, y as u128, 1, |a, b| Some(a.wrapping_mul(b))).unwrap(),
+						Operator::Neg => y.wrapping_neg(),
+						Operator::Abs => y.wrapping_abs(),
+						Operator::Min => z.min(y),
+						Operator::Max => z.max(y),
+						_ => unreachable!("handled by 'logic'"),
+					}),
+					OverflowPolicy::Saturating => Some(match *op {
+						Operator::Add => z.saturating_add(y),
+						Operator::Sub => z.saturating_sub(y),
+						Operator::Mul => z.saturating_mul(y),
+						Operator::Div => z.saturating_div(y),
+						// the true remainder always fits, only MIN % -1 overflows on the way
+						Operator::Rem => z.wrapping_rem(y),
+						Operator::Pow => match pow_by_squaring(z, y as u128, 1, |a, b| a.checked_mul(b)) {
+							Some(x) => x,
+							// a negative base to an odd power is negative
+							None if z < 0 && y % 2 == 1 => $t::MIN,
+							None => $t::MAX,
+						},
+						Operator::Neg => y.saturating_neg(),
+						Operator::Abs => y.saturating_abs(),
+						Operator::Min => z.min(y),
+						Operator::Max => z.max(y),
+						_ => unreachable!("handled by 'logic'"),
+					}),
+				};
+				result.ok_or(ErrorKind::Overflow)
+			}
+
+			fn to_big(&self) -> Option<BigInt> {
+				Some(BigInt::from(*self))
+			}
+
+			fn from_big(big: &BigInt) -> Option<$t> {
+				$t::try_from(big).ok()
+			}
+		}
+	)*}
+}
+
+signed_number!(i8 i16 i32 i64 i128 isize);
+
+// Floats follow IEEE 754: dividing by zero gives an infinity or NaN rather than an error,
+// and the overflow policy does not apply.
+macro_rules! float_number {
+	($($t:ident)*) => {$(
+		impl Number for $t {
+			fn apply(op: &Operator, args: &[$t], _policy: OverflowPolicy) -> Result<$t, ErrorKind> {
+				let (z, y) = (args[0], args[args.len() - 1]);
+				if let Some(b) = logic(op, &z, &y, &0.0) {
+					return Ok(if b { 1.0 } else { 0.0 });
+				}
+				Ok(match *op {
+					Operator::Add => z + y,
+					Operator::Sub => z - y,
+					Operator::Mul => z * y,
+					Operator::Div => z / y,
+					Operator::Rem => z % y,
+					Operator::Pow => z.powf(y),
+					Operator::Neg => -y,
+					Operator::Abs => y.abs(),
+					Operator::Min => z.min(y),
+					Operator::Max => z.max(y),
+					_ => unreachable!("handled by 'logic'"),
+				})
+			}
+		}
+	)*}
+}
+
+float_number!(f32 f64);
+
+/// 'BigInt' never overflows, except that '^' reports 'ErrorKind::Overflow' for exponents
+/// above 'MAX_BIG_EXPONENT' (unless the base is 0, 1 or -1) to keep memory bounded.
+impl Number for BigInt {
+	fn apply(op: &Operator, args: &[BigInt], _policy: OverflowPolicy) -> Result<BigInt, ErrorKind> {
+		let (z, y) = (&args[0], &args[args.len() - 1]);
+		if let Some(b) = logic(op, z, y, &BigInt::zero()) {
+			return Ok(BigInt::from(b as u8));
+		}
+		check_domain(op, y.is_zero(), y.is_negative())?;
+		Ok(match *op {
+			Operator::Add => z + y,
+			Operator::Sub => z - y,
+			Operator::Mul => z * y,
+			Operator::Div => z / y,
+			Operator::Rem => z % y,
+			Operator::Pow => {
+				// the exponent may be too big for any machine integer, so settle the
+				// bases whose powers stay small first
+				if z.is_zero() {
+					if y.is_zero() { BigInt::one() } else { BigInt::zero() }
+				} else if z.is_one() {
+					BigInt::one()
+				} else if *z == -BigInt::one() {
+					if y.is_even() { BigInt::one() } else { -BigInt::one() }
+				} else if *y > BigInt::from(MAX_BIG_EXPONENT) {
+					return Err(ErrorKind::Overflow);
+				} else {
+					let exp = y.to_u128().unwrap();
+					pow_by_squaring(z.clone(), exp, BigInt::one(), |a, b| Some(a * b)).unwrap()
+				}
+			}
+			Operator::Neg => -y,
+			Operator::Abs => y.abs(),
+			Operator::Min => z.min(y).clone(),
+			Operator::Max => z.max(y).clone(),
+			_ => unreachable!("handled by 'logic'"),
+		})
+	}
+
+	fn to_big(&self) -> Option<BigInt> {
+		Some(self.clone())
+	}
+
+	fn from_big(big: &BigInt) -> Option<BigInt> {
+		Some(big.clone())
+	}
+}
+
+/// Rationals always use checked arithmetic, whatever the overflow policy. '^' takes an
+/// integer exponent, which may be negative.
+impl<T> Number for Ratio<T>
+	where T: Clone + Integer + Signed + CheckedAdd + CheckedSub + CheckedMul + ToPrimitive {
+	fn apply(op: &Operator, args: &[Ratio<T>], _policy: OverflowPolicy) -> Result<Ratio<T>, ErrorKind> {
+		let (z, y) = (&args[0], &args[args.len() - 1]);
+		if let Some(b) = logic(op, z, y, &Ratio::zero()) {
+			return Ok(if b { Ratio::one() } else { Ratio::zero() });
+		}
+		check_domain(op, y.is_zero(), false)?;
+		let zero = Ratio::zero();
+		let result = match *op {
+			Operator::Add => z.checked_add(y),
+			Operator::Sub => z.checked_sub(y),
+			Operator::Mul => z.checked_mul(y),
+			Operator::Div => z.checked_div(y),
+			// z - y * trunc(z / y), so the sign follows the dividend like the integers
+			Operator::Rem => z.checked_div(y)
+				.and_then(|q| q.trunc().checked_mul(y))
+				.and_then(|p| z.checked_sub(&p)),
+			Operator::Pow => {
+				if !y.is_integer() {
+					return Err(ErrorKind::Unsupported);
+				}
+				let exp = y.to_integer().to_i128().ok_or(ErrorKind::Overflow)?;
+				if exp < 0 && z.is_zero() {
+					return Err(ErrorKind::DivideByZero);
+				}
+				if exp.unsigned_abs() > MAX_BIG_EXPONENT as u128 && !z.is_zero() && z.abs() != Ratio::one() {
+					return Err(ErrorKind::Overflow);
+				}
+				let power = pow_by_squaring(z.clone(), exp.unsigned_abs(), Ratio::one(), |a, b| a.checked_mul(&b));
+				if exp < 0 {
+					power.and_then(|p| Ratio::one().checked_div(&p))
+				} else {
+					power
+				}
+			}
+			Operator::Neg => zero.checked_sub(y),
+			Operator::Abs => if y.is_negative() { zero.checked_sub(y) } else { Some(y.clone()) },
+			Operator::Min => Some(z.min(y).clone()),
+			Operator::Max => Some(z.max(y).clone()),
+			_ => unreachable!("handled by 'logic'"),
+		};
+		result.ok_or(ErrorKind::Overflow)
+	}
+}
+
+/// Complex numbers support '+', '-', '*' and 'neg', applying the overflow policy to each
+/// part. The other operators, including the comparisons, are 'ErrorKind::Unsupported'.
+impl<T: Number + Copy> Number for Complex<T> {
+	fn apply(op: &Operator, args: &[Complex<T>], policy: OverflowPolicy) -> Result<Complex<T>, ErrorKind> {
+		let (z, y) = (args[0], args[args.len() - 1]);
+		let part = |op: Operator, a: T, b: T| T::apply(&op, &[a, b], policy);
+		match *op {
+			Operator::Add => Ok(Complex::new(part(Operator::Add, z.re(), y.re())?, part(Operator::Add, z.im(), y.im())?)),
+			Operator::Sub => Ok(Complex::new(part(Operator::Sub, z.re(), y.re())?, part(Operator::Sub, z.im(), y.im())?)),
+			Operator::Mul => {
+				let re = part(Operator::Sub, part(Operator::Mul, z.re(), y.re())?, part(Operator::Mul, z.im(), y.im())?)?;
+				let im = part(Operator::Add, part(Operator::Mul, z.re(), y.im())?, part(Operator::Mul, z.im(), y.re())?)?;
+				Ok(Complex::new(re, im))
+			}
+			Operator::Neg => Ok(Complex::new(T::apply(op, &[y.re()], policy)?, T::apply(op, &[y.im()], policy)?)),
+			_ => Err(ErrorKind::Unsupported),
+		}
+	}
+}
+
+// Applies a comparison or logical operator, where 'zero' is false and anything else is
+// true. Returns 'None' for the other operators.
+fn logic<V: PartialOrd>(op: &Operator, z: &V, y: &V, zero: &V) -> Option<bool> {
+	Some(match *op {
+		Operator::Eq => z == y,
+		Operator::Ne => z != y,
+		Operator::Lt => z < y,
+		Operator::Le => z <= y,
+		Operator::Gt => z > y,
+		Operator::Ge => z >= y,
+		Operator::And => z != zero && y != zero,
+		Operator::Or => z != zero || y != zero,
+		// unary, so only the top of the stack
+		Operator::Not => y == zero,
+		_ => return None,
+	})
+}
+
+// Rejects operands that are invalid whatever the overflow policy, given whether the top
+// of the stack is zero or negative.
+fn check_domain(op: &Operator, top_is_zero: bool, top_is_negative: bool) -> Result<(), ErrorKind> {
+	match *op {
+		Operator::Div | Operator::Rem if top_is_zero => Err(ErrorKind::DivideByZero),
+		Operator::Pow if top_is_negative => Err(ErrorKind::NegativeExponent),
+		_ => Ok(()),
+	}
+}
+
+// Computes 'base' to the power 'exp' by repeated squaring, stopping as soon as 'mul'
+// returns 'None'.
+fn pow_by_squaring<V, M>(base: V, mut exp: u128, one: V, mul: M) -> Option<V>
+	where V: Clone, M: Fn(V, V) -> Option<V> {
+	let mut result = one;
+	let mut base = base;
+	while exp > 0 {
+		if exp & 1 == 1 {
+			result = mul(result, base.clone())?;
+		}
+		exp >>= 1;
+		if exp > 0 {
+			base = mul(base.clone(), base)?;
+		}
+	}
+	Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use {eval, eval_with, try_eval, EvalError, EvalOptions, Token};
+
+	#[test]
+	fn other_integer_widths() {
+		let tokens = [Token::Operand(100i8), Token::Operand(2), Token::Operator(Operator::Mul)];
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::Overflow }), try_eval(&tokens));
+		assert_eq!(Ok(-56), eval_with(&tokens, &EvalOptions { overflow: OverflowPolicy::Wrapping }));
+
+		let tokens = [Token::Operand(1i128 << 100), Token::Operand(3), Token::Operator(Operator::Div)];
+		assert_eq!(Some((1i128 << 100) / 3), eval(&tokens));
+	}
+
+	#[test]
+	fn floats() {
+		let tokens = [Token::Operand(1.5), Token::Operand(2.0), Token::Operator(Operator::Pow),
+			Token::Operand(0.25), Token::Operator(Operator::Max)];
+		assert_eq!(Some(2.25), eval(&tokens));
+		let tokens = [Token::Operand(1.0f64), Token::Operand(0.0), Token::Operator(Operator::Div)];
+		assert_eq!(Some(f64::INFINITY), eval(&tokens));
+	}
+
+	#[test]
+	fn rationals() {
+		let r = |n: i64, d: i64| Token::Operand(Ratio::new(n, d));
+		let tokens = [r(1, 2), r(1, 3), Token::Operator(Operator::Add), r(-2, 1), Token::Operator(Operator::Pow)];
+		assert_eq!(Some(Ratio::new(36, 25)), eval(&tokens));
+		let tokens = [r(7, 2), r(1, 1), Token::Operator(Operator::Rem)];
+		assert_eq!(Some(Ratio::new(1, 2)), eval(&tokens));
+		let tokens = [r(1, 2), r(1, 2), Token::Operator(Operator::Pow)];
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::Unsupported }), try_eval(&tokens));
+		let tokens = [r(i64::MAX, 1), r(2, 1), Token::Operator(Operator::Mul)];
+		assert_eq!(Err(EvalError { index: 2, kind: ErrorKind::Overflow }), try_eval(&tokens));
+	}
+
+	#[test]
+	fn complex() {
+		// 1+2j + (-2-3j) * (2+3j), as in hw2ex
+		let tokens = [
+			Token::Operand(Complex::new(1, 2)),
+			Token::Operand(Complex::new(-2, -3)),
+			Token::Operand(Complex::new(2, 3)),
+			Token::Operator(Operator::Mul),
+			Token::Operator(Operator::Add),
+		];
+		assert_eq!(Some(Complex::new(6, -10)), eval(&tokens));
+
+		let tokens = [Token::Operand(Complex::new(1.0, 2.0)), Token::Operator(Operator::Abs)];
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::Unsupported }), try_eval(&tokens));
+	}
+
+	#[test]
+	fn big_integers() {
+		let big = BigInt::from(isize::MAX);
+		let tokens = [Token::Operand(big.clone()), Token::Operand(big.clone()), Token::Operator(Operator::Mul)];
+		assert_eq!(Some(&big * &big), eval(&tokens));
+	}
+}
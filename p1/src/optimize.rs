@@ -0,0 +1,207 @@
+use {Number, Operator, OverflowPolicy, StackOp, Token};
+
+/// Folds constant sub-expressions and removes identities such as 'x 0 +' and 'x 1 *'.
+///
+/// The optimized program gives the same result as 'tokens' under every
+/// 'OverflowPolicy', and fails with the same 'ErrorKind' when 'tokens' fails, though
+/// the error's token index may move. An operation is only folded if it succeeds under
+/// 'OverflowPolicy::Checked', so '9223372036854775807 1 +' is left for evaluation to
+/// report or wrap.
+pub fn optimize<T: Number>(tokens: &[Token<T>]) -> Vec<Token<T>> {
+	let mut out = Vec::with_capacity(tokens.len());
+	// the stack as far as it is known: 'Some' for a value that is the constant pushed by
+	// a single token in 'out', 'None' for any other value
+	let mut stack: Vec<Option<T>> = Vec::new();
+	// whether there may be more values below 'stack', e.g. after a word call
+	let mut unknown = false;
+	for token in tokens {
+		match *token {
+			Token::Operand(ref x) => {
+				out.push(token.clone());
+				stack.push(Some(x.clone()));
+			}
+			Token::Operator(ref op) => {
+				let n = op.arity();
+				if stack.len() >= n {
+					let base = stack.len() - n;
+					if let Some(x) = fold(op, &stack[base..]) {
+						out.truncate(out.len() - n);
+						out.push(Token::Operand(x.clone()));
+						stack.truncate(base);
+						stack.push(Some(x));
+						continue;
+					}
+					// 'x c op' with a value 'x' below the constant 'c'
+					if n == 2 && stack[base + 1].as_ref().is_some_and(|c| is_identity(op, c)) {
+						out.pop();
+						stack.pop();
+						continue;
+					}
+				}
+				out.push(token.clone());
+				pop(&mut stack, &mut unknown, n);
+				stack.push(None);
+			}
+			Token::Var(_) => {
+				out.push(token.clone());
+				stack.push(None);
+			}
+			Token::Store(_) => {
+				out.push(token.clone());
+				pop(&mut stack, &mut unknown, 1);
+				forget(&mut stack);
+			}
+			Token::Stack(ref op) => {
+				out.push(token.clone());
+				if let StackOp::Clear = *op {
+					stack.clear();
+					unknown = false;
+				} else {
+					pop(&mut stack, &mut unknown, op.depth());
+					forget(&mut stack);
+					// only the number of values pushed back is tracked, not their order
+					let pushed = match *op {
+						StackOp::Dup | StackOp::Over | StackOp::Pick(_) => op.depth() + 1,
+						StackOp::Swap | StackOp::Rot => op.depth(),
+						_ => 0,
+					};
+					stack.extend((0..pushed).map(|_| None));
+				}
+			}
+			// a word can do anything to the stack, and a conditional may or may not run
+			// what follows
+			Token::Call(_) | Token::If | Token::Else | Token::Then => {
+				out.push(token.clone());
+				stack.clear();
+				unknown = true;
+			}
+		}
+	}
+	out
+}
+
+// Applies 'op' to constant arguments, if they are all known and it succeeds.
+fn fold<T: Number>(op: &Operator, args: &[Option<T>]) -> Option<T> {
+	let args = args.iter().cloned().collect::<Option<Vec<T>>>()?;
+	T::apply(op, &args, OverflowPolicy::Checked).ok()
+}
+
+// Returns whether 'x c op' always gives 'x'.
+fn is_identity<T: Number>(op: &Operator, c: &T) -> bool {
+	let same = |op| T::apply(&op, &[c.clone(), c.clone()], OverflowPolicy::Checked).as_ref() == Ok(c);
+	match *op {
+		// 'c - c == c' only for zero; 'c + c' would also match infinities
+		Operator::Add | Operator::Sub => same(Operator::Sub),
+		// 'c / c == c' only for one, and fails for types without division
+		Operator::Mul | Operator::Div => same(Operator::Div),
+		Operator::Pow => same(Operator::Div) && same(Operator::Pow),
+		_ => false,
+	}
+}
+
+// Pops 'n' values off the known stack. Popping more than are known leaves only what is
+// unknown.
+fn pop<T>(stack: &mut Vec<Option<T>>, unknown: &mut bool, n: usize) {
+	if stack.len() < n {
+		stack.clear();
+		*unknown = true;
+	} else {
+		let len = stack.len() - n;
+		stack.truncate(len);
+	}
+}
+
+// Marks every known value as not a constant, after a token that moves values around
+// without them staying the last tokens of the program.
+fn forget<T>(stack: &mut [Option<T>]) {
+	for x in stack {
+		*x = None;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use proptest::prelude::*;
+
+	use super::*;
+	use {eval_with_env, parse_postfix, Env, ErrorKind, EvalOptions};
+
+	fn optimized(text: &str) -> String {
+		let tokens = optimize(&parse_postfix(text).unwrap());
+		tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" ")
+	}
+
+	#[test]
+	fn folds_constants() {
+		assert_eq!("6", optimized("2 3 *"));
+		assert_eq!("x 14 +", optimized("x 2 3 4 * + +"));
+		assert_eq!("x -2 min", optimized("x 2 neg min"));
+		assert_eq!("1 x ->y 2 +", optimized("1 x ->y 2 +"));
+		assert_eq!("1 2 swap -", optimized("1 2 swap -"));
+		assert_eq!("8 ->x x", optimized("2 3 ^ ->x x"));
+	}
+
+	#[test]
+	fn keeps_failing_operations() {
+		assert_eq!("1 0 /", optimized("1 0 /"));
+		assert_eq!("9223372036854775807 1 +", optimized("9223372036854775807 1 +"));
+		assert_eq!("2 -1 ^", optimized("2 -1 ^"));
+	}
+
+	#[test]
+	fn removes_identities() {
+		assert_eq!("x", optimized("x 0 +"));
+		assert_eq!("x", optimized("x 1 * 0 - 1 / 1 ^"));
+		assert_eq!("x y *", optimized("x y 2 2 - + *"));
+		// only when there is a value for the constant to apply to
+		assert_eq!("0 +", optimized("0 +"));
+		assert_eq!("0 x ->y +", optimized("0 x ->y +"));
+		assert_eq!("0 x +", optimized("0 x +"));
+		assert_eq!("x 2 *", optimized("x 2 *"));
+	}
+
+	// A few operands that overflow easily, so folding is tested at the edges.
+	fn token() -> impl Strategy<Value = Token> {
+		prop_oneof![
+			(-3isize..4).prop_map(Token::Operand),
+			prop::sample::select(vec![isize::MAX, isize::MIN, 1 << 40]).prop_map(Token::Operand),
+			prop::sample::select(vec!["+", "-", "*", "/", "%", "^", "neg", "abs", "min", "max"])
+				.prop_map(|s| Token::Operator(Operator::from_symbol(s).unwrap())),
+			prop::sample::select(vec![StackOp::Dup, StackOp::Drop, StackOp::Swap, StackOp::Over, StackOp::Rot, StackOp::Pick(1), StackOp::Clear])
+				.prop_map(Token::Stack),
+			prop::sample::select(vec!["x", "y"]).prop_map(|s| Token::Var(s.to_string())),
+			prop::sample::select(vec!["x", "y"]).prop_map(|s| Token::Store(s.to_string())),
+		]
+	}
+
+	fn policy() -> impl Strategy<Value = OverflowPolicy> {
+		prop::sample::select(vec![OverflowPolicy::Checked, OverflowPolicy::Wrapping, OverflowPolicy::Saturating, OverflowPolicy::Arbitrary])
+	}
+
+	proptest! {
+		#[test]
+		fn optimizing_keeps_results(tokens in prop::collection::vec(token(), 0..24), x in any::<isize>(), overflow in policy()) {
+			let options = EvalOptions { overflow };
+			let mut env = Env::new();
+			env.insert("x".to_string(), x);
+			env.insert("y".to_string(), 3);
+			let mut optimized_env = env.clone();
+			let before = eval_with_env(&tokens, &mut env, &options).map_err(|e| e.kind);
+			let after = eval_with_env(&optimize(&tokens), &mut optimized_env, &options).map_err(|e| e.kind);
+			prop_assert_eq!(before, after);
+			prop_assert_eq!(env, optimized_env);
+		}
+
+		#[test]
+		fn optimizing_never_grows(tokens in prop::collection::vec(token(), 0..24)) {
+			prop_assert!(optimize(&tokens).len() <= tokens.len());
+		}
+	}
+
+	#[test]
+	fn keeps_error_kinds() {
+		let options = EvalOptions::default();
+		let tokens = parse_postfix("1 2 + 3 0 / x").unwrap();
+		assert_eq!(Err(ErrorKind::DivideByZero), eval_with_env(&optimize(&tokens), &mut Env::new(), &options).map_err(|e| e.kind));
+	}
+}
@@ -0,0 +1,225 @@
+// Property tests across the whole crate: valid programs are generated from a small
+// expression grammar and checked against a tree-walking evaluator written independently
+// of 'Number', and random token and text soups check that nothing panics.
+use proptest::prelude::*;
+
+use {eval_traced, eval_with_env, eval_with_words, optimize, parse_postfix, parse_program, to_infix, CompiledProgram,
+	Dictionary, Env, Evaluator, ErrorKind, EvalError, EvalOptions, Operator, OverflowPolicy, StackOp, Token};
+
+// A program from the grammar. Each node becomes the postfix tokens of its children
+// followed by its own, and 'If' becomes 'c if a else b then'.
+#[derive(Clone, Debug)]
+enum Expr {
+	Num(isize),
+	Var(&'static str),
+	Unary(Operator, Box<Expr>),
+	Binary(Operator, Box<Expr>, Box<Expr>),
+	If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+	fn tokens(&self, out: &mut Vec<Token>) {
+		match *self {
+			Expr::Num(x) => out.push(Token::Operand(x)),
+			Expr::Var(name) => out.push(Token::Var(name.to_string())),
+			Expr::Unary(op, ref y) => {
+				y.tokens(out);
+				out.push(Token::Operator(op));
+			}
+			Expr::Binary(op, ref z, ref y) => {
+				z.tokens(out);
+				y.tokens(out);
+				out.push(Token::Operator(op));
+			}
+			Expr::If(ref c, ref a, ref b) => {
+				c.tokens(out);
+				out.push(Token::If);
+				a.tokens(out);
+				out.push(Token::Else);
+				b.tokens(out);
+				out.push(Token::Then);
+			}
+		}
+	}
+
+	fn len(&self) -> usize {
+		match *self {
+			Expr::Num(_) | Expr::Var(_) => 1,
+			Expr::Unary(_, ref y) => y.len() + 1,
+			Expr::Binary(_, ref z, ref y) => z.len() + y.len() + 1,
+			Expr::If(ref c, ref a, ref b) => c.len() + a.len() + b.len() + 3,
+		}
+	}
+
+	// Evaluates the tree with 'OverflowPolicy::Checked' semantics. 'pos' is the index of
+	// the node's first token, so errors carry the same index 'eval' reports.
+	fn reference(&self, env: &Env, pos: usize) -> Result<isize, EvalError> {
+		match *self {
+			Expr::Num(x) => Ok(x),
+			Expr::Var(name) => env.get(name).cloned().ok_or(EvalError { index: pos, kind: ErrorKind::UndefinedVariable }),
+			Expr::Unary(op, ref y) => {
+				let y = y.reference(env, pos)?;
+				apply(op, 0, y).map_err(|kind| EvalError { index: pos + self.len() - 1, kind })
+			}
+			Expr::Binary(op, ref z, ref y) => {
+				let zv = z.reference(env, pos)?;
+				let yv = y.reference(env, pos + z.len())?;
+				apply(op, zv, yv).map_err(|kind| EvalError { index: pos + self.len() - 1, kind })
+			}
+			Expr::If(ref c, ref a, ref b) => {
+				if c.reference(env, pos)? != 0 {
+					a.reference(env, pos + c.len() + 1)
+				} else {
+					b.reference(env, pos + c.len() + a.len() + 2)
+				}
+			}
+		}
+	}
+}
+
+// Applies 'op' exactly in 'i128' and checks the result fits, rather than going through
+// 'Number'. Unary operators use 'y'.
+fn apply(op: Operator, z: isize, y: isize) -> Result<isize, ErrorKind> {
+	let (z, y) = (z as i128, y as i128);
+	let exact = match op {
+		Operator::Add => z + y,
+		Operator::Sub => z - y,
+		Operator::Mul => z * y,
+		Operator::Div | Operator::Rem if y == 0 => return Err(ErrorKind::DivideByZero),
+		Operator::Div => z / y,
+		Operator::Rem if z / y > isize::MAX as i128 => return Err(ErrorKind::Overflow),
+		Operator::Rem => z % y,
+		Operator::Pow if y < 0 => return Err(ErrorKind::NegativeExponent),
+		Operator::Pow => match z {
+			0 if y > 0 => 0,
+			0 | 1 => 1,
+			-1 => if y % 2 == 0 { 1 } else { -1 },
+			// 2 ^ 64 is already too big
+			_ if y >= 64 => return Err(ErrorKind::Overflow),
+			_ => z.checked_pow(y as u32).ok_or(ErrorKind::Overflow)?,
+		},
+		Operator::Neg => -y,
+		Operator::Abs => y.abs(),
+		Operator::Min => z.min(y),
+		Operator::Max => z.max(y),
+		Operator::Eq => (z == y) as i128,
+		Operator::Ne => (z != y) as i128,
+		Operator::Lt => (z < y) as i128,
+		Operator::Le => (z <= y) as i128,
+		Operator::Gt => (z > y) as i128,
+		Operator::Ge => (z >= y) as i128,
+		Operator::And => (z != 0 && y != 0) as i128,
+		Operator::Or => (z != 0 || y != 0) as i128,
+		Operator::Not => (y == 0) as i128,
+	};
+	if exact < isize::MIN as i128 || exact > isize::MAX as i128 {
+		return Err(ErrorKind::Overflow);
+	}
+	Ok(exact as isize)
+}
+
+fn symbols(symbols: &[&str]) -> impl Strategy<Value = Operator> {
+	prop::sample::select(symbols.iter().map(|s| Operator::from_symbol(s).unwrap()).collect::<Vec<_>>())
+}
+
+fn operand() -> impl Strategy<Value = isize> {
+	prop_oneof![
+		4 => -3isize..4,
+		1 => prop::sample::select(vec![isize::MAX, isize::MIN, 1 << 40, 64]),
+	]
+}
+
+fn expr() -> impl Strategy<Value = Expr> {
+	let leaf = prop_oneof![
+		operand().prop_map(Expr::Num),
+		prop::sample::select(vec!["x", "y"]).prop_map(Expr::Var),
+	];
+	leaf.prop_recursive(5, 48, 3, |inner| prop_oneof![
+		(symbols(&["neg", "abs", "not"]), inner.clone()).prop_map(|(op, y)| Expr::Unary(op, Box::new(y))),
+		(symbols(&["+", "-", "*", "/", "%", "^", "min", "max", "=", "<>", "<", "<=", ">", ">=", "and", "or"]), inner.clone(), inner.clone())
+			.prop_map(|(op, z, y)| Expr::Binary(op, Box::new(z), Box::new(y))),
+		(inner.clone(), inner.clone(), inner).prop_map(|(c, a, b)| Expr::If(Box::new(c), Box::new(a), Box::new(b))),
+	])
+}
+
+// Any token, so most programs are malformed.
+fn token() -> impl Strategy<Value = Token> {
+	prop_oneof![
+		operand().prop_map(Token::Operand),
+		symbols(&["+", "-", "*", "/", "%", "^", "neg", "abs", "min", "max", "<", "=", "and", "not"]).prop_map(Token::Operator),
+		prop::sample::select(vec![StackOp::Dup, StackOp::Drop, StackOp::Swap, StackOp::Over, StackOp::Rot, StackOp::Pick(2), StackOp::Clear])
+			.prop_map(Token::Stack),
+		prop::sample::select(vec!["x", "y", "z"]).prop_map(|s| Token::Var(s.to_string())),
+		prop::sample::select(vec!["x", "y"]).prop_map(|s| Token::Store(s.to_string())),
+		prop::sample::select(vec!["square", "loop", "nothing"]).prop_map(|s| Token::Call(s.to_string())),
+		prop::sample::select(vec![Token::If, Token::Else, Token::Then]),
+	]
+}
+
+fn policy() -> impl Strategy<Value = OverflowPolicy> {
+	prop::sample::select(vec![OverflowPolicy::Checked, OverflowPolicy::Wrapping, OverflowPolicy::Saturating, OverflowPolicy::Arbitrary])
+}
+
+fn env(x: isize) -> Env {
+	let mut env = Env::new();
+	env.insert("x".to_string(), x);
+	env.insert("y".to_string(), 3);
+	env
+}
+
+fn words() -> Dictionary {
+	let mut words = Dictionary::new();
+	words.define("square", parse_postfix("dup *").unwrap());
+	words.define("loop", vec![Token::Call("loop".to_string())]);
+	words
+}
+
+proptest! {
+	#[test]
+	fn agrees_with_reference(e in expr(), x in any::<isize>()) {
+		let mut tokens = Vec::new();
+		e.tokens(&mut tokens);
+		let expected = e.reference(&env(x), 0);
+		let options = EvalOptions::default();
+		prop_assert_eq!(&expected, &eval_with_env(&tokens, &mut env(x), &options));
+		prop_assert_eq!(&expected, &CompiledProgram::new(&tokens).unwrap().eval(&mut env(x), &options));
+		prop_assert_eq!(&expected, &eval_traced(&tokens, env(x), &options).result);
+		let mut evaluator = Evaluator::with_env(env(x), &options);
+		let fed = tokens.iter().cloned().try_for_each(|t| evaluator.feed(t));
+		prop_assert_eq!(&expected, &fed.and_then(|()| evaluator.finish()));
+		// arbitrary precision only differs where an intermediate value overflows
+		if let Ok(value) = expected {
+			prop_assert_eq!(Ok(value), eval_with_env(&tokens, &mut env(x), &EvalOptions { overflow: OverflowPolicy::Arbitrary }));
+		}
+	}
+
+	#[test]
+	fn random_programs_never_panic(tokens in prop::collection::vec(token(), 0..24), x in any::<isize>(), overflow in policy()) {
+		let options = EvalOptions { overflow };
+		let words = words();
+		let result = eval_with_words(&tokens, &mut env(x), &words, &options);
+		let _ = optimize(&tokens);
+		let _ = to_infix(&tokens);
+		// a program that compiles, with its inputs bound, has nothing left to fail on but
+		// its values, which 'eval' meets in the same order
+		if let Ok(program) = CompiledProgram::with_words(&tokens, &words) {
+			let compiled = program.eval(&mut env(x), &options);
+			if overflow != OverflowPolicy::Arbitrary && !program.inputs().contains(&"z") {
+				prop_assert_eq!(result, compiled);
+			}
+		}
+	}
+
+	#[test]
+	fn random_text_never_panics(text in "[-+*/%^<>=a-z0-9():; .>]{0,40}") {
+		if let Ok(tokens) = parse_postfix(&text) {
+			let printed = tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" ");
+			prop_assert_eq!(Ok(tokens.clone()), parse_postfix(&printed));
+			let _ = eval_with_env(&tokens, &mut env(1), &EvalOptions::default());
+		}
+		let mut words = Dictionary::new();
+		if let Ok(tokens) = parse_program(&text, &mut words) {
+			let _ = eval_with_words(&tokens, &mut env(1), &words, &EvalOptions::default());
+		}
+	}
+}
@@ -0,0 +1,75 @@
+// Serde support for the 'serde' feature. Operators and stack words are written as the
+// text 'parse_postfix' reads, e.g. "+" and "pick(2)", so the JSON stays readable and does
+// not change if the enums are reordered.
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use {Operator, StackOp};
+
+impl Serialize for Operator {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.symbol())
+	}
+}
+
+impl<'de> Deserialize<'de> for Operator {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Operator, D::Error> {
+		deserializer.deserialize_str(Word("an operator symbol such as \"+\"", Operator::from_symbol))
+	}
+}
+
+impl Serialize for StackOp {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> Deserialize<'de> for StackOp {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StackOp, D::Error> {
+		deserializer.deserialize_str(Word("a stack word such as \"dup\"", StackOp::from_word))
+	}
+}
+
+// Reads a string with 'parse', described as 'expected' in errors.
+struct Word<F>(&'static str, F);
+
+impl<'de, V, F: FnOnce(&str) -> Option<V>> de::Visitor<'de> for Word<F> {
+	type Value = V;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+
+	fn visit_str<E: de::Error>(self, s: &str) -> Result<V, E> {
+		let expected = self.0;
+		(self.1)(s).ok_or_else(|| E::invalid_value(de::Unexpected::Str(s), &expected))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json;
+
+	use {parse_postfix, Token};
+
+	#[test]
+	fn json_shape() {
+		let mut tokens = parse_postfix("x 2 ^ dup pick(1) ->y if y else 0 then f").unwrap();
+		tokens[11] = Token::Call("f".to_string());
+		let json = serde_json::to_string(&tokens).unwrap();
+		assert_eq!(r#"[{"var":"x"},{"operand":2},{"operator":"^"},{"stack":"dup"},{"stack":"pick(1)"},{"store":"y"},"if",{"var":"y"},"else",{"operand":0},"then",{"call":"f"}]"#, json);
+		assert_eq!(tokens, serde_json::from_str::<Vec<Token>>(&json).unwrap());
+
+		let floats: Vec<Token<f64>> = serde_json::from_str(r#"[{"operand":1.5},{"operand":2},{"operator":"min"}]"#).unwrap();
+		assert_eq!(Token::Operand(2.0), floats[1]);
+	}
+
+	#[test]
+	fn rejects_unknown_words() {
+		let e = serde_json::from_str::<Token>(r#"{"operator":"**"}"#).unwrap_err();
+		assert!(e.to_string().contains("expected an operator symbol"), "{}", e);
+		assert!(serde_json::from_str::<Token>(r#"{"stack":"pick(x)"}"#).is_err());
+		assert!(serde_json::from_str::<Token>(r#"{"operand":"1"}"#).is_err());
+	}
+}
@@ -0,0 +1,225 @@
+use {single_value, step, Dictionary, Env, ErrorKind, EvalError, EvalOptions, Number, Token};
+
+/// Evaluates the postfix tokens as they come from 'tokens', reporting why it failed.
+///
+/// Unlike 'try_eval' the program is never held in memory, so it can be read from a file
+/// or generated on the fly; see 'Evaluator' for how the result can differ.
+pub fn eval_iter<T: Number, I: IntoIterator<Item = Token<T>>>(tokens: I) -> Result<T, EvalError> {
+	let mut evaluator = Evaluator::new(&EvalOptions::default());
+	for token in tokens {
+		evaluator.feed(token)?;
+	}
+	evaluator.finish()
+}
+
+/// Evaluates a postfix program fed to it one token at a time.
+///
+/// Only the stack, the variables and the conditionals still open are kept, so memory
+/// does not grow with the length of the program. Without the rest of the program to
+/// look ahead in, an 'if' that is never closed is only reported by 'finish', and a
+/// malformed program may fail with a different error than 'eval_with_env' gives.
+/// 'OverflowPolicy::Arbitrary' is applied to each operation, as with 'exec'.
+pub struct Evaluator<T = isize> {
+	stack: Vec<T>,
+	env: Env<T>,
+	words: Dictionary<T>,
+	// the number of tokens fed so far, and so the index of the next one
+	pos: usize,
+	// the conditionals being run, innermost last
+	open: Vec<Open>,
+	// the branch being skipped, if any
+	skip: Option<Skip>,
+	options: EvalOptions,
+	error: Option<EvalError>,
+}
+
+// A conditional whose branch is being run.
+struct Open {
+	// the index of its 'if'
+	at: usize,
+	// whether the 'else' has been fed
+	in_else: bool,
+}
+
+// A branch not taken, whose tokens are read without running them.
+struct Skip {
+	at: usize,
+	in_else: bool,
+	// how many conditionals inside the branch are open
+	nested: usize,
+}
+
+impl<T: Number> Evaluator<T> {
+	/// Creates an evaluator with an empty stack and no variables.
+	pub fn new(options: &EvalOptions) -> Evaluator<T> {
+		Evaluator::with_env(Env::new(), options)
+	}
+
+	/// Creates an evaluator that reads and stores variables in 'env'.
+	pub fn with_env(env: Env<T>, options: &EvalOptions) -> Evaluator<T> {
+		Evaluator { stack: Vec::new(), env, words: Dictionary::new(), pos: 0, open: Vec::new(), skip: None, options: *options, error: None }
+	}
+
+	/// Makes the evaluator run 'Call' tokens from 'words'.
+	pub fn with_words(mut self, words: Dictionary<T>) -> Evaluator<T> {
+		self.words = words;
+		self
+	}
+
+	/// Runs the next token of the program, or skips it if it is in a branch not taken.
+	///
+	/// A token that fails leaves the stack as it was before it, even a call that failed part
+	/// way through its word. Once a token has failed, every later call returns the same
+	/// error without running its token.
+	pub fn feed(&mut self, token: Token<T>) -> Result<(), EvalError> {
+		if let Some(e) = self.error {
+			return Err(e);
+		}
+		let index = self.pos;
+		self.pos += 1;
+		self.run(token, index).map_err(|kind| {
+			let e = EvalError { index, kind };
+			self.error = Some(e);
+			e
+		})
+	}
+
+	fn run(&mut self, token: Token<T>, index: usize) -> Result<(), ErrorKind> {
+		if let Some(ref mut skip) = self.skip {
+			match token {
+				Token::If => skip.nested += 1,
+				Token::Then if skip.nested > 0 => skip.nested -= 1,
+				Token::Then => self.skip = None,
+				// an 'if' has at most one 'else'
+				Token::Else if skip.nested == 0 && skip.in_else => return Err(ErrorKind::UnmatchedConditional),
+				Token::Else if skip.nested == 0 => {
+					self.open.push(Open { at: skip.at, in_else: true });
+					self.skip = None;
+				}
+				_ => {}
+			}
+			return Ok(());
+		}
+		match token {
+			Token::If => {
+				let c = self.stack.pop().ok_or(ErrorKind::Underflow)?;
+				if c.is_true() {
+					self.open.push(Open { at: index, in_else: false });
+				} else {
+					self.skip = Some(Skip { at: index, in_else: false, nested: 0 });
+				}
+			}
+			// the end of the 'if' branch, so skip the 'else' branch
+			Token::Else => match self.open.pop() {
+				Some(Open { at, in_else: false }) => self.skip = Some(Skip { at, in_else: true, nested: 0 }),
+				_ => return Err(ErrorKind::UnmatchedConditional),
+			},
+			Token::Then => {
+				self.open.pop().ok_or(ErrorKind::UnmatchedConditional)?;
+			}
+			// a word may fail part way through its body, after changing the stack
+			Token::Call(..) => {
+				let before = self.stack.clone();
+				if let Err(kind) = step(&token, &mut self.stack, &mut self.env, &self.words, 0, self.options.overflow) {
+					self.stack = before;
+					return Err(kind);
+				}
+			}
+			token => step(&token, &mut self.stack, &mut self.env, &self.words, 0, self.options.overflow)?,
+		}
+		Ok(())
+	}
+
+	/// Ends the program and returns the single value left on the stack.
+	pub fn finish(self) -> Result<T, EvalError> {
+		if let Some(e) = self.error {
+			return Err(e);
+		}
+		let unclosed = self.skip.as_ref().map(|s| s.at).or_else(|| self.open.last().map(|o| o.at));
+		if let Some(index) = unclosed {
+			return Err(EvalError { index, kind: ErrorKind::UnmatchedConditional });
+		}
+		single_value(self.stack, self.pos)
+	}
+
+	/// Returns the number of tokens fed so far.
+	pub fn position(&self) -> usize {
+		self.pos
+	}
+
+	/// Returns the current stack, bottom first.
+	pub fn stack(&self) -> &[T] {
+		&self.stack
+	}
+
+	/// Returns the current variable bindings.
+	pub fn env(&self) -> &Env<T> {
+		&self.env
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::iter;
+
+	use super::*;
+	use {eval_with_words, parse_postfix, parse_program, Operator, StackOp};
+
+	#[test]
+	fn agrees_with_eval() {
+		for text in &["1 2 + 3 *", "4 ->x x x * 1 -", "0 if 1 else 2 then 3 +", "1 if 0 if 5 else 6 then else 7 then",
+			"2 0 if 1 + then", ": sq dup * ; 3 sq 1 if sq then", "1 +", "1 2", "1 0 /"] {
+			let mut words = Dictionary::new();
+			let tokens = parse_program(text, &mut words).unwrap();
+			let options = EvalOptions::default();
+			let mut evaluator = Evaluator::new(&options).with_words(words.clone());
+			let fed = tokens.iter().cloned().try_for_each(|t| evaluator.feed(t));
+			let streamed = fed.and_then(|()| evaluator.finish());
+			assert_eq!(eval_with_words(&tokens, &mut Env::new(), &words, &options), streamed, "{}", text);
+		}
+	}
+
+	#[test]
+	fn feeds_one_token_at_a_time() {
+		let mut evaluator = Evaluator::new(&EvalOptions::default());
+		for token in parse_postfix("4 ->x x x").unwrap() {
+			evaluator.feed(token).unwrap();
+		}
+		assert_eq!(Some(&4), evaluator.env().get("x"));
+		assert_eq!(&[4, 4], evaluator.stack());
+		assert_eq!(4, evaluator.position());
+
+		// once a token fails, later tokens are not run
+		let e = EvalError { index: 4, kind: ErrorKind::Underflow };
+		assert_eq!(Err(e), evaluator.feed(Token::Stack(StackOp::Rot)));
+		assert_eq!(Err(e), evaluator.feed(Token::Operand(1)));
+		assert_eq!(5, evaluator.position());
+		assert_eq!(&[4, 4], evaluator.stack());
+		assert_eq!(Err(e), evaluator.finish());
+
+		// a word that fails part way through its body leaves the stack alone
+		let mut words = Dictionary::new();
+		let tokens = parse_program(": bad 1 + dup 0 / ; 5 bad", &mut words).unwrap();
+		let mut evaluator = Evaluator::new(&EvalOptions::default()).with_words(words);
+		evaluator.feed(tokens[0].clone()).unwrap();
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::DivideByZero }), evaluator.feed(tokens[1].clone()));
+		assert_eq!(&[5], evaluator.stack());
+	}
+
+	#[test]
+	fn unmatched_conditionals() {
+		let run = |text| eval_iter(parse_postfix(text).unwrap());
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::UnmatchedConditional }), run("1 if 2"));
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::UnmatchedConditional }), run("0 if 2 else 3"));
+		assert_eq!(Err(EvalError { index: 5, kind: ErrorKind::UnmatchedConditional }), run("1 if 2 else 3 else 4 then"));
+		assert_eq!(Err(EvalError { index: 1, kind: ErrorKind::UnmatchedConditional }), run("1 then"));
+	}
+
+	#[test]
+	fn long_programs() {
+		// 1 followed by a million '1 +', never collected
+		let tokens = iter::once(Token::Operand(1))
+			.chain(iter::repeat_n(vec![Token::Operand(1), Token::Operator(Operator::Add)], 1_000_000).flatten());
+		assert_eq!(Ok(1_000_001), eval_iter(tokens));
+	}
+}
@@ -0,0 +1,310 @@
+// Symbolic differentiation and simplification of integer expressions.
+use {ErrorKind, Expr, Number, Operator, OverflowPolicy};
+
+impl Expr {
+	/// Returns the derivative with respect to 'var', simplified.
+	///
+	/// '+', '-', '*', '/', 'neg' and '^' with an exponent that does not mention 'var' can be
+	/// differentiated; other operators fail with 'ErrorKind::Unsupported'. '/' is treated
+	/// as exact division.
+	pub fn derivative(&self, var: &str) -> Result<Expr, ErrorKind> {
+		derive(self, var).map(|d| d.simplify())
+	}
+
+	/// Returns an equivalent expression with constants folded, identities such as 'x * 1'
+	/// and 'x + 0' removed, and like terms collected, so 'x + 2 * x' becomes '3 * x' and
+	/// 'x * x' becomes 'x ^ 2'.
+	///
+	/// The result has the same value wherever the original evaluates without error, but
+	/// may succeed where it failed: 'x / 0 * 0' becomes '0'. As with 'optimize', an
+	/// operation is only folded if it does not overflow.
+	pub fn simplify(&self) -> Expr {
+		match *self {
+			Expr::Literal(_) | Expr::Var(_) => self.clone(),
+			Expr::Unary(op, ref y) => unary(op, y.simplify()),
+			Expr::Binary(op, ref z, ref y) => binary(op, z.simplify(), y.simplify()),
+		}
+	}
+
+	// Returns whether the variable 'var' appears in the expression.
+	fn mentions(&self, var: &str) -> bool {
+		match *self {
+			Expr::Literal(_) => false,
+			Expr::Var(ref name) => name == var,
+			Expr::Unary(_, ref y) => y.mentions(var),
+			Expr::Binary(_, ref z, ref y) => z.mentions(var) || y.mentions(var),
+		}
+	}
+}
+
+// Returns the derivative of 'e' with respect to 'var', before simplifying.
+fn derive(e: &Expr, var: &str) -> Result<Expr, ErrorKind> {
+	use Operator::{Add, Div, Mul, Neg, Pow, Sub};
+	let d = |e: &Expr| derive(e, var);
+	Ok(match *e {
+		Expr::Literal(_) => Expr::Literal(0),
+		Expr::Var(ref name) => Expr::Literal(if name == var { 1 } else { 0 }),
+		Expr::Unary(Neg, ref y) => Expr::unary(Neg, d(y)?),
+		Expr::Binary(op, ref z, ref y) if op == Add || op == Sub => Expr::binary(op, d(z)?, d(y)?),
+		// z'y + zy'
+		Expr::Binary(Mul, ref z, ref y) => Expr::binary(Add,
+			Expr::binary(Mul, d(z)?, (**y).clone()),
+			Expr::binary(Mul, (**z).clone(), d(y)?)),
+		// (z'y - zy') / y^2
+		Expr::Binary(Div, ref z, ref y) => Expr::binary(Div,
+			Expr::binary(Sub, Expr::binary(Mul, d(z)?, (**y).clone()), Expr::binary(Mul, (**z).clone(), d(y)?)),
+			Expr::binary(Pow, (**y).clone(), Expr::Literal(2))),
+		// y z^(y-1) z'
+		Expr::Binary(Pow, ref z, ref y) if !y.mentions(var) => Expr::binary(Mul,
+			Expr::binary(Mul, (**y).clone(), Expr::binary(Pow, (**z).clone(), Expr::binary(Sub, (**y).clone(), Expr::Literal(1)))),
+			d(z)?),
+		_ => return Err(ErrorKind::Unsupported),
+	})
+}
+
+// Applies 'op' to constants, if it succeeds without overflowing.
+fn fold(op: Operator, args: &[isize]) -> Option<Expr> {
+	isize::apply(&op, args, OverflowPolicy::Checked).ok().map(Expr::Literal)
+}
+
+// Simplifies 'op y', where 'y' is already simplified.
+fn unary(op: Operator, y: Expr) -> Expr {
+	if let Expr::Literal(c) = y {
+		if let Some(e) = fold(op, &[c]) {
+			return e;
+		}
+	}
+	match op {
+		Operator::Neg => sum(Expr::unary(op, y)),
+		_ => Expr::unary(op, y),
+	}
+}
+
+// Simplifies 'z y op', where 'z' and 'y' are already simplified.
+fn binary(op: Operator, z: Expr, y: Expr) -> Expr {
+	if let (&Expr::Literal(a), &Expr::Literal(b)) = (&z, &y) {
+		if let Some(e) = fold(op, &[a, b]) {
+			return e;
+		}
+	}
+	match (op, &y) {
+		(Operator::Add, _) | (Operator::Sub, _) => sum(Expr::binary(op, z, y)),
+		(Operator::Mul, _) => product(Expr::binary(op, z, y)),
+		(Operator::Div, &Expr::Literal(1)) | (Operator::Pow, &Expr::Literal(1)) => z,
+		(Operator::Pow, &Expr::Literal(0)) => Expr::Literal(1),
+		_ => Expr::binary(op, z, y),
+	}
+}
+
+// Collects the like terms of a sum, e.g. 'x + 1 - 3 * x' into '-2 * x + 1'. The constant
+// goes last. If a coefficient would overflow, 'e' is returned as it is.
+fn sum(e: Expr) -> Expr {
+	// each term is a coefficient and what it multiplies, 'None' for the constant
+	let mut terms: Vec<(isize, Option<Expr>)> = Vec::new();
+	if add_terms(&e, 1, &mut terms).is_none() {
+		return e;
+	}
+	if let Some(i) = terms.iter().position(|t| t.1.is_none()) {
+		let constant = terms.remove(i);
+		terms.push(constant);
+	}
+	let mut out: Option<Expr> = None;
+	for (c, x) in terms.into_iter().filter(|t| t.0 != 0) {
+		out = Some(match out {
+			None if c == -1 && x.is_some() => Expr::unary(Operator::Neg, x.unwrap()),
+			None => term(c, x),
+			Some(acc) => match c.checked_neg() {
+				Some(n) if c < 0 => Expr::binary(Operator::Sub, acc, term(n, x)),
+				_ => Expr::binary(Operator::Add, acc, term(c, x)),
+			},
+		});
+	}
+	out.unwrap_or(Expr::Literal(0))
+}
+
+// Adds the terms of 'e', times 'sign', to 'terms'.
+fn add_terms(e: &Expr, sign: isize, terms: &mut Vec<(isize, Option<Expr>)>) -> Option<()> {
+	let (c, x) = match *e {
+		Expr::Binary(Operator::Add, ref z, ref y) => return add_terms(z, sign, terms).and_then(|()| add_terms(y, sign, terms)),
+		Expr::Binary(Operator::Sub, ref z, ref y) => return add_terms(z, sign, terms).and_then(|()| add_terms(y, -sign, terms)),
+		Expr::Unary(Operator::Neg, ref y) => return add_terms(y, -sign, terms),
+		Expr::Literal(c) => (c, None),
+		_ => {
+			let (c, x) = coefficient(e);
+			(c, Some(x))
+		}
+	};
+	let c = c.checked_mul(sign)?;
+	match terms.iter_mut().find(|t| t.1 == x) {
+		Some(t) => t.0 = t.0.checked_add(c)?,
+		None => terms.push((c, x)),
+	}
+	Some(())
+}
+
+// Splits a product into its literal coefficient and the rest, e.g. '3 * x * y' into 3
+// and 'x * y'.
+fn coefficient(e: &Expr) -> (isize, Expr) {
+	match *e {
+		Expr::Binary(Operator::Mul, ref z, ref y) => match **z {
+			Expr::Literal(c) => (c, (**y).clone()),
+			_ => {
+				let (c, z) = coefficient(z);
+				(c, Expr::binary(Operator::Mul, z, (**y).clone()))
+			}
+		},
+		_ => (1, e.clone()),
+	}
+}
+
+// Returns 'c * x', or 'c' alone for the constant.
+fn term(c: isize, x: Option<Expr>) -> Expr {
+	match x {
+		None => Expr::Literal(c),
+		Some(x) if c == 1 => x,
+		Some(x) => product(Expr::binary(Operator::Mul, Expr::Literal(c), x)),
+	}
+}
+
+// Collects the constants and like factors of a product, e.g. '2 * x * 3 * x' into
+// '6 * x ^ 2'. If the coefficient or a power would overflow, 'e' is returned as it is.
+fn product(e: Expr) -> Expr {
+	let mut c = 1;
+	// each factor is a base and a power of at least 1
+	let mut factors: Vec<(Expr, isize)> = Vec::new();
+	if add_factors(&e, &mut c, &mut factors).is_none() {
+		return e;
+	}
+	if c == 0 {
+		return Expr::Literal(0);
+	}
+	let mut xs: Vec<Expr> = factors.into_iter()
+		.map(|(x, n)| if n == 1 { x } else { Expr::binary(Operator::Pow, x, Expr::Literal(n)) })
+		.collect();
+	if xs.is_empty() {
+		return Expr::Literal(c);
+	}
+	match c {
+		1 => {}
+		// '-x * y' rather than '-1 * x * y'
+		-1 => xs[0] = unary(Operator::Neg, xs[0].clone()),
+		_ => xs.insert(0, Expr::Literal(c)),
+	}
+	let first = xs.remove(0);
+	xs.into_iter().fold(first, |acc, x| Expr::binary(Operator::Mul, acc, x))
+}
+
+// Multiplies the constants of 'e' into 'c' and adds its other factors to 'factors'.
+fn add_factors(e: &Expr, c: &mut isize, factors: &mut Vec<(Expr, isize)>) -> Option<()> {
+	let (x, n) = match *e {
+		Expr::Binary(Operator::Mul, ref z, ref y) => return add_factors(z, c, factors).and_then(|()| add_factors(y, c, factors)),
+		Expr::Unary(Operator::Neg, ref y) => {
+			*c = c.checked_neg()?;
+			return add_factors(y, c, factors);
+		}
+		Expr::Literal(x) => {
+			*c = c.checked_mul(x)?;
+			return Some(());
+		}
+		Expr::Binary(Operator::Pow, ref x, ref y) => match **y {
+			Expr::Literal(n) if n >= 1 => ((**x).clone(), n),
+			_ => (e.clone(), 1),
+		},
+		_ => (e.clone(), 1),
+	};
+	match factors.iter_mut().find(|f| f.0 == x) {
+		Some(f) => f.1 = f.1.checked_add(n)?,
+		None => factors.push((x, n)),
+	}
+	Some(())
+}
+
+#[cfg(test)]
+mod tests {
+	use proptest::prelude::*;
+
+	use p3::InfixToken;
+	use {parse_postfix, Env, EvalOptions};
+	use super::*;
+
+	fn tree(text: &str) -> Expr {
+		Expr::from_postfix(&parse_postfix(text).unwrap()).unwrap()
+	}
+
+	fn simplified(text: &str) -> String {
+		tree(text).simplify().to_string()
+	}
+
+	fn derivative(text: &str) -> String {
+		tree(text).derivative("x").unwrap().to_string()
+	}
+
+	#[test]
+	fn simplifies() {
+		assert_eq!("x", simplified("x 0 + 1 *"));
+		assert_eq!("x", simplified("0 x + 1 / 1 ^"));
+		assert_eq!("1", simplified("x 0 ^"));
+		assert_eq!("0", simplified("x y * 0 *"));
+		assert_eq!("2 * x", simplified("x x +"));
+		assert_eq!("0", simplified("x x -"));
+		assert_eq!("5 * x + 6", simplified("2 x * 2 3 * + 3 x * +"));
+		assert_eq!("-2 * x - y + 1", simplified("x 1 + 3 x * - y -"));
+		assert_eq!("6 * x ^ 3", simplified("2 x * 3 * x x * *"));
+		assert_eq!("x", simplified("x neg neg"));
+		assert_eq!("-x * y", simplified("x neg y *"));
+		// folding stops where it would overflow or fail
+		assert_eq!("9223372036854775807 + 1", simplified("9223372036854775807 1 +"));
+		assert_eq!("x / 0", simplified("x 0 /"));
+	}
+
+	#[test]
+	fn differentiates() {
+		assert_eq!("3 * x ^ 2 + 2", derivative("x 3 ^ 2 x * + 5 +"));
+		assert_eq!("y", derivative("x y *"));
+		assert_eq!("2 * x", derivative("x x *"));
+		assert_eq!("4 * (2 * x + 1)", derivative("x 2 * 1 + 2 ^"));
+		assert_eq!("-1 / x ^ 2", derivative("1 x /"));
+		assert_eq!("-1", derivative("y x - 7 +"));
+		assert_eq!("0", derivative("y 2 ^"));
+		assert_eq!(Err(ErrorKind::Unsupported), tree("2 x ^").derivative("x"));
+		assert_eq!(Err(ErrorKind::Unsupported), tree("x 3 %").derivative("x"));
+	}
+
+	#[test]
+	fn from_p3() {
+		use p3::Operator::{Add, Mul};
+		// 2 * 3 + 4
+		let infix = [InfixToken::Operand(2), InfixToken::Operator(Mul), InfixToken::Operand(3), InfixToken::Operator(Add), InfixToken::Operand(4)];
+		let e = Expr::from_infix(&infix).unwrap();
+		assert_eq!("10", e.simplify().to_string());
+		assert_eq!(Ok(Expr::Literal(0)), e.derivative("x"));
+	}
+
+	// Sums, products and powers of small values, which wrapping arithmetic computes
+	// exactly modulo 2^64.
+	fn ring() -> impl Strategy<Value = Expr> {
+		let leaf = prop_oneof![
+			(-3isize..4).prop_map(Expr::Literal),
+			prop::sample::select(vec!["x", "y"]).prop_map(Expr::var),
+		];
+		leaf.prop_recursive(4, 32, 2, |inner| prop_oneof![
+			inner.clone().prop_map(|y| Expr::unary(Operator::Neg, y)),
+			(prop::sample::select(vec![Operator::Add, Operator::Sub, Operator::Mul]), inner.clone(), inner.clone())
+				.prop_map(|(op, z, y)| Expr::binary(op, z, y)),
+			(inner, 0isize..4).prop_map(|(z, n)| Expr::binary(Operator::Pow, z, Expr::Literal(n))),
+		])
+	}
+
+	proptest! {
+		#[test]
+		fn simplifying_keeps_values(e in ring(), x in any::<isize>(), y in any::<isize>()) {
+			let mut env = Env::new();
+			env.insert("x".to_string(), x);
+			env.insert("y".to_string(), y);
+			let options = EvalOptions { overflow: OverflowPolicy::Wrapping };
+			let simple = e.simplify();
+			prop_assert_eq!(e.eval(&env, &options), simple.eval(&env, &options), "{} became {}", e, simple);
+			prop_assert_eq!(&simple, &simple.simplify());
+		}
+	}
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use {Env, Token};
+
+/// How deeply user words may call each other before evaluation fails with
+/// 'ErrorKind::RecursionLimit'.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// User-defined words, e.g. 'square' from ': square dup * ;'.
+///
+/// A program calls a word with 'Token::Call'; the word's body runs in its place, on the
+/// same stack and variables.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dictionary<T = isize> {
+	words: HashMap<String, Vec<Token<T>>>,
+	max_depth: usize,
+}
+
+impl<T> Dictionary<T> {
+	/// Creates an empty dictionary that allows 'DEFAULT_MAX_DEPTH' nested calls.
+	pub fn new() -> Dictionary<T> {
+		Dictionary::with_max_depth(DEFAULT_MAX_DEPTH)
+	}
+
+	/// Creates an empty dictionary that allows 'max_depth' nested calls.
+	pub fn with_max_depth(max_depth: usize) -> Dictionary<T> {
+		Dictionary { words: HashMap::new(), max_depth }
+	}
+
+	/// Defines the word 'name' to run 'body'.
+	///
+	/// Returns 'false' if 'name' is already defined, leaving the old definition in place, and 'true' otherwise.
+	pub fn define(&mut self, name: &str, body: Vec<Token<T>>) -> bool {
+		if self.words.contains_key(name) {
+			return false;
+		}
+		self.words.insert(name.to_string(), body);
+		true
+	}
+
+	/// Returns the body of the word 'name', if it is defined.
+	pub fn get(&self, name: &str) -> Option<&[Token<T>]> {
+		self.words.get(name).map(|body| &body[..])
+	}
+
+	/// Returns whether the word 'name' is defined.
+	pub fn contains(&self, name: &str) -> bool {
+		self.words.contains_key(name)
+	}
+
+	/// Returns how many nested calls are allowed.
+	pub fn max_depth(&self) -> usize {
+		self.max_depth
+	}
+
+	/// Returns the names 'tokens' reads as variables that are not words, not bound in 'env'
+	/// and not stored by 'tokens' or the words it calls, each with the index of the
+	/// top-level token that reads it.
+	///
+	/// 'parse_program' reads a name that is not a word as a variable, so these are likely
+	/// misspelled words rather than missing inputs.
+	pub fn unknown_names(&self, tokens: &[Token<T>], env: &Env<T>) -> Vec<(usize, String)> {
+		let mut reads = Vec::new();
+		let mut stored = Vec::new();
+		// each word is looked into once, however often it is called
+		let mut called = Vec::new();
+		for (index, token) in tokens.iter().enumerate() {
+			let mut pending = vec![token];
+			while let Some(token) = pending.pop() {
+				match *token {
+					Token::Var(ref name) => reads.push((index, name)),
+					Token::Store(ref name) => stored.push(name),
+					Token::Call(ref name) if !called.contains(&name) => {
+						called.push(name);
+						pending.extend(self.get(name).unwrap_or(&[]));
+					}
+					_ => {}
+				}
+			}
+		}
+		reads.into_iter()
+			.filter(|&(_, name)| !self.contains(name) && !env.contains_key(name) && !stored.contains(&name))
+			.map(|(index, name)| (index, name.clone()))
+			.collect()
+	}
+
+	/// Returns the defined words and their bodies, in no particular order.
+	pub fn iter(&self) -> impl Iterator<Item = (&str, &[Token<T>])> {
+		self.words.iter().map(|(name, body)| (&name[..], &body[..]))
+	}
+}
+
+impl<T> Default for Dictionary<T> {
+	fn default() -> Dictionary<T> {
+		Dictionary::new()
+	}
+}
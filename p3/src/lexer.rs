@@ -0,0 +1,228 @@
+// Reads infix text such as "(1 + 2) * -3 / max(x, 4)" into tokens.
+use std::{error, fmt};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::CharIndices;
+
+use {InfixToken, OperatorTable};
+
+// The bytes a token was read from.
+pub type Span = Range<usize>;
+
+// Why some infix text could not be read.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LexErrorKind {
+	// a character that does not start any token
+	UnexpectedChar(char),
+	// a number that does not fit in an 'isize'
+	OutOfRange(String),
+}
+
+// An error from 'tokenize', with the bytes of the offending character or number.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexError {
+	pub span: Span,
+	pub kind: LexErrorKind,
+}
+
+impl fmt::Display for LexError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.kind {
+			LexErrorKind::UnexpectedChar(c) => write!(f, "unexpected character '{}' at byte {}", c, self.span.start),
+			LexErrorKind::OutOfRange(ref number) => write!(f, "number '{}' out of range at byte {}", number, self.span.start),
+		}
+	}
+}
+
+impl error::Error for LexError {}
+
+// Reads an infix expression such as "(1 + 2) * -3 / max(x, 4)"; whitespace between
+// tokens is optional.
+//
+// A name of letters, digits and '_' that does not start with a digit is a function if
+// the next token is '(', and a variable otherwise.
+//
+// A '-' directly followed by a digit is a negative literal where an operand is expected:
+// at the start, after an operator, '(' or ','. Anywhere else it is subtraction, so
+// "1 -2" is '1 - 2'. Before a '^' it is unary minus, so "-2 ^ 2" is '-(2 ^ 2)'.
+//
+// The operators are the built-in ones; 'tokenize_with' reads those of another table.
+pub fn tokenize(input: &str) -> Result<Vec<InfixToken>, LexError> {
+	tokenize_with(input, &OperatorTable::new())
+}
+
+// Reads an infix expression like 'tokenize', also returning the bytes of each token so
+// errors from 'infix_to_postfix' can be shown with 'ParseError::render_source'.
+pub fn tokenize_spans(input: &str) -> Result<(Vec<InfixToken>, Vec<Span>), LexError> {
+	tokenize_spans_with(input, &OperatorTable::new())
+}
+
+// Reads an infix expression like 'tokenize', with the binary operators of 'table', for
+// 'infix_to_postfix_with'. Where symbols overlap the longest one is read, and a name that
+// is the symbol of an operator, such as 'mod', is that operator rather than a variable.
+pub fn tokenize_with(input: &str, table: &OperatorTable) -> Result<Vec<InfixToken>, LexError> {
+	tokenize_spans_with(input, table).map(|(tokens, _)| tokens)
+}
+
+// Reads an infix expression like 'tokenize_with', also returning the bytes of each token
+// as 'tokenize_spans' does.
+pub fn tokenize_spans_with(input: &str, table: &OperatorTable) -> Result<(Vec<InfixToken>, Vec<Span>), LexError> {
+	let mut tokens = Vec::new();
+	let mut spans = Vec::new();
+	let mut chars = input.char_indices().peekable();
+	while let Some((start, c)) = chars.next() {
+		let operand_expected = matches!(tokens.last(), None | Some(&InfixToken::Operator(..)) | Some(&InfixToken::LeftParen) | Some(&InfixToken::Comma));
+		let token = match c {
+			_ if c.is_whitespace() => continue,
+			'(' => InfixToken::LeftParen,
+			')' => InfixToken::RightParen,
+			',' => InfixToken::Comma,
+			'-' if operand_expected && negative_literal(&input[start + 1..]) => number(input, start, &mut chars)?,
+			_ if c.is_ascii_digit() => number(input, start, &mut chars)?,
+			_ if c.is_alphabetic() || c == '_' => {
+				while chars.peek().is_some_and(|&(_, d)| d.is_alphanumeric() || d == '_') {
+					chars.next();
+				}
+				let end = chars.peek().map_or(input.len(), |&(i, _)| i);
+				let name = input[start..end].to_string();
+				if let Some(op) = table.find(&name).filter(|op| op.symbol() == name) {
+					InfixToken::Operator(op)
+				} else if input[end..].trim_start().starts_with('(') {
+					InfixToken::Function(name)
+				} else {
+					InfixToken::Var(name)
+				}
+			}
+			_ => match table.find(&input[start..]) {
+				Some(op) => {
+					let end = start + op.symbol().len();
+					while chars.peek().is_some_and(|&(i, _)| i < end) {
+						chars.next();
+					}
+					InfixToken::Operator(op)
+				}
+				None => return Err(LexError { span: start..start + c.len_utf8(), kind: LexErrorKind::UnexpectedChar(c) }),
+			},
+		};
+		tokens.push(token);
+		spans.push(start..chars.peek().map_or(input.len(), |&(i, _)| i));
+	}
+	Ok((tokens, spans))
+}
+
+// Whether the '-' before 'rest' is the sign of a number: it is followed by digits, and
+// not by a '^' after them, since '^' binds tighter than unary minus.
+fn negative_literal(rest: &str) -> bool {
+	let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+	digits > 0 && !rest[digits..].trim_start().starts_with('^')
+}
+
+// Reads the rest of the number starting at 'start', whose first character has been read.
+fn number(input: &str, start: usize, chars: &mut Peekable<CharIndices>) -> Result<InfixToken, LexError> {
+	while chars.peek().is_some_and(|&(_, d)| d.is_ascii_digit()) {
+		chars.next();
+	}
+	let end = chars.peek().map_or(input.len(), |&(i, _)| i);
+	let text = &input[start..end];
+	text.parse().map(InfixToken::Operand)
+		.map_err(|_| LexError { span: start..end, kind: LexErrorKind::OutOfRange(text.to_string()) })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use {infix_to_postfix, infix_to_postfix_with, Assoc, Operator, PostfixToken};
+
+	#[test]
+	fn reads_tokens() {
+		let expected = vec![InfixToken::LeftParen, InfixToken::Operand(1), InfixToken::Operator(Operator::Add), InfixToken::Operand(2),
+			InfixToken::RightParen, InfixToken::Operator(Operator::Mul), InfixToken::Operand(-3), InfixToken::Operator(Operator::Div),
+			InfixToken::Operand(4)];
+		assert_eq!(Ok(expected.clone()), tokenize("(1 + 2) * -3 / 4"));
+		assert_eq!(Ok(expected), tokenize("(1+2)*-3/4"));
+		assert_eq!(Ok(vec![InfixToken::Operand(-12), InfixToken::Operator(Operator::Sub), InfixToken::Operand(340)]), tokenize("  -12 -340\t"));
+		assert_eq!(Ok(vec![InfixToken::Operand(1), InfixToken::Operator(Operator::Sub), InfixToken::Operand(-2)]), tokenize("1 - -2"));
+		// a '-' apart from its digits is always an operator
+		assert_eq!(Ok(vec![InfixToken::Operator(Operator::Sub), InfixToken::Operand(3)]), tokenize("- 3"));
+		assert_eq!(Ok(vec![]), tokenize(" "));
+		assert_eq!(Ok(vec![InfixToken::Function("max".to_string()), InfixToken::LeftParen, InfixToken::Var("x_1".to_string()), InfixToken::Comma,
+			InfixToken::Operand(-4), InfixToken::RightParen]), tokenize("max (x_1,-4)"));
+		// '^' binds tighter than the sign
+		assert_eq!(Ok(vec![InfixToken::Operator(Operator::Sub), InfixToken::Operand(2), InfixToken::Operator(Operator::Pow), InfixToken::Operand(-2)]),
+			tokenize("-2 ^ -2"));
+	}
+
+	#[test]
+	fn tracks_spans() {
+		let (tokens, spans) = tokenize_spans("(10+2) * -3").unwrap();
+		assert_eq!(7, tokens.len());
+		assert_eq!(vec![0..1, 1..3, 3..4, 4..5, 5..6, 7..8, 9..11], spans);
+	}
+
+	#[test]
+	fn reads_calls() {
+		let postfix = |text| infix_to_postfix(&tokenize(text).unwrap());
+		assert_eq!(Ok(vec![PostfixToken::Operand(1), PostfixToken::Operand(2), PostfixToken::Operand(3), PostfixToken::Operator(Operator::Mul),
+			PostfixToken::Function("max".to_string(), 2)]), postfix("max(1, 2 * 3)"));
+		assert_eq!(Ok(vec![PostfixToken::Var("x".to_string()), PostfixToken::Var("y".to_string()), PostfixToken::Operator(Operator::Sub),
+			PostfixToken::Function("abs".to_string(), 1)]), postfix("abs(x - y)"));
+
+		let source = "min(1, 2, 3)";
+		let (tokens, spans) = tokenize_spans(source).unwrap();
+		let e = infix_to_postfix(&tokens).unwrap_err();
+		assert_eq!("min(1, 2, 3)\n^^^ function takes 2 argument(s) but was given 3", e.render_source(source, &spans));
+	}
+
+	#[test]
+	fn reports_errors() {
+		let e = tokenize("1 + $").unwrap_err();
+		assert_eq!(LexError { span: 4..5, kind: LexErrorKind::UnexpectedChar('$') }, e);
+		assert_eq!("unexpected character '$' at byte 4", e.to_string());
+		assert_eq!(Err(LexError { span: 3..5, kind: LexErrorKind::UnexpectedChar('°') }), tokenize("1 +°"));
+		let e = tokenize("2 * 99999999999999999999").unwrap_err();
+		assert_eq!(4..24, e.span);
+		assert_eq!("number '99999999999999999999' out of range at byte 4", e.to_string());
+	}
+
+	#[test]
+	fn parse_errors_point_into_the_source() {
+		let source = "(1 + 2) *  * 3";
+		let (tokens, spans) = tokenize_spans(source).unwrap();
+		let e = infix_to_postfix(&tokens).unwrap_err();
+		assert_eq!("(1 + 2) *  * 3\n        ^^^^ missing operand between '*' and '*'", e.render_source(source, &spans));
+
+		let source = "(1 + 2";
+		let (tokens, spans) = tokenize_spans(source).unwrap();
+		let e = infix_to_postfix(&tokens).unwrap_err();
+		assert_eq!("(1 + 2\n^ '(' is never closed", e.render_source(source, &spans));
+
+		assert_eq!(Ok(vec![PostfixToken::Operand(7), PostfixToken::Operand(-2), PostfixToken::Operator(Operator::Mul)]),
+			infix_to_postfix(&tokenize("7*-2").unwrap()));
+		assert_eq!(Ok(vec![PostfixToken::Operand(7), PostfixToken::Operand(2), PostfixToken::Operator(Operator::Neg), PostfixToken::Operator(Operator::Mul)]),
+			infix_to_postfix(&tokenize("7 * - 2").unwrap()));
+		assert_eq!(Ok(vec![PostfixToken::Operand(2), PostfixToken::Operand(3), PostfixToken::Operand(2), PostfixToken::Operator(Operator::Pow),
+			PostfixToken::Operator(Operator::Pow), PostfixToken::Operator(Operator::Neg)]), infix_to_postfix(&tokenize("-2^3^2").unwrap()));
+	}
+
+	#[test]
+	fn reads_table_operators() {
+		let mut table = OperatorTable::new();
+		for symbol in &["%", "**", "mod"] {
+			table.define(Operator::Custom(symbol.to_string()), 2, Assoc::Left, 2);
+		}
+		let op = |symbol: &str| InfixToken::Operator(Operator::Custom(symbol.to_string()));
+		// the longest symbol wins, so '**' is not two '*'
+		assert_eq!(Ok(vec![InfixToken::Operand(7), op("%"), InfixToken::Operand(2), op("**"), InfixToken::Operand(-3),
+			InfixToken::Operator(Operator::Mul), InfixToken::Var("x".to_string())]), tokenize_with("7%2 ** -3*x", &table));
+		// a name is an operator only if it is the whole symbol
+		assert_eq!(Ok(vec![InfixToken::Var("x".to_string()), op("mod"), InfixToken::Var("modulus".to_string())]), tokenize_with("x mod modulus", &table));
+		assert_eq!(Ok(vec![PostfixToken::Operand(1), PostfixToken::Operand(7), PostfixToken::Operand(3), PostfixToken::Operator(Operator::Custom("%".to_string())),
+			PostfixToken::Operator(Operator::Add)]), infix_to_postfix_with(&tokenize_with("1 + 7 % 3", &table).unwrap(), &table));
+
+		// the built-in table has none of them
+		assert_eq!(Err(LexError { span: 1..2, kind: LexErrorKind::UnexpectedChar('%') }), tokenize("7%2"));
+		assert_eq!(Ok(vec![InfixToken::Var("x".to_string()), InfixToken::Var("mod".to_string())]), tokenize("x mod"));
+		let (_, spans) = tokenize_spans_with("2**3", &table).unwrap();
+		assert_eq!(vec![0..1, 1..3, 3..4], spans);
+	}
+}
@@ -1,4 +1,12 @@
-#[derive(Clone, Copy, Debug, PartialEq)]
+use std::{error, fmt};
+
+pub use lexer::{tokenize, tokenize_spans, tokenize_spans_with, tokenize_with, LexError, LexErrorKind, Span};
+pub use table::{Assoc, OperatorInfo, OperatorTable};
+
+mod lexer;
+mod table;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Operator {
 	// '+' 
 	Add,
@@ -8,80 +16,284 @@ pub enum Operator {
 	Mul,
 	// '/'
 	Div,
+	// unary minus, read from a '-' where an operand is expected
+	Neg,
+	// '^'
+	Pow,
+	// an operator added to an 'OperatorTable', named by its symbol
+	Custom(String),
 }
 
 
 // if op1 on top is equal or greater, than pop!!
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum InfixToken {
 	Operator(Operator),
 	Operand(isize),
 	LeftParen,
 	RightParen,
+	// a variable, such as 'x'
+	Var(String),
+	// the name of a function; its arguments follow in parentheses, as in 'max(1, 2)'
+	Function(String),
+	// separates the arguments of a function
+	Comma,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum PostfixToken {
 	Operator(Operator),
 	Operand(isize),
+	Var(String),
+	// a call of the function, with how many arguments it takes off the stack
+	Function(String, usize),
+}
+
+impl Operator {
+	// how the operator is written; 'Sub' and 'Neg' are both '-'
+	pub fn symbol(&self) -> &str {
+		match *self {
+			Operator::Add => "+",
+			Operator::Sub | Operator::Neg => "-",
+			Operator::Mul => "*",
+			Operator::Div => "/",
+			Operator::Pow => "^",
+			Operator::Custom(ref symbol) => symbol,
+		}
+	}
+}
+
+impl fmt::Display for Operator {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.symbol())
+	}
 }
 
-impl InfixToken {
-	fn precedence(&self) -> i32 {
-		match *self { 
-			InfixToken::Operator(Operator::Add) | InfixToken::Operator(Operator::Sub) => 1,
-			InfixToken::Operator(Operator::Mul) | InfixToken::Operator(Operator::Div) => 2,
-			_ => 0
+impl fmt::Display for InfixToken {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			InfixToken::Operator(ref op) => write!(f, "{}", op),
+			InfixToken::Operand(x) => write!(f, "{}", x),
+			InfixToken::LeftParen => write!(f, "("),
+			InfixToken::RightParen => write!(f, ")"),
+			InfixToken::Var(ref name) | InfixToken::Function(ref name) => write!(f, "{}", name),
+			InfixToken::Comma => write!(f, ","),
+		}
+	}
+}
+
+// What is wrong with an infix expression.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseErrorKind {
+	// there are no tokens
+	Empty,
+	// the first token is a binary operator, ',' or ')' (rule 2a)
+	BadStart,
+	// a token cannot follow the one before it (rule 2b); 'ParseError::pair' holds both
+	BadPair,
+	// the last token is an operator, a function name, '(' or ','
+	BadEnd,
+	// a ')' closes nothing
+	UnmatchedRightParen,
+	// a '(' is never closed
+	UnmatchedLeftParen,
+	// an operator missing from the 'OperatorTable'
+	UnknownOperator,
+	// a function missing from the 'OperatorTable'
+	UnknownFunction,
+	// a function is called with the wrong number of arguments
+	Arity { expected: usize, found: usize },
+	// a ',' outside the parentheses of a function call
+	MisplacedComma,
+}
+
+// Why 'infix_to_postfix' rejected an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+	// index of the offending token; for 'BadPair' the second token of the pair
+	pub index: usize,
+	pub kind: ParseErrorKind,
+	// for 'BadPair', the two adjacent tokens that broke rule 2b
+	pub pair: Option<(InfixToken, InfixToken)>,
+}
+
+impl ParseError {
+	fn at(index: usize, kind: ParseErrorKind) -> ParseError {
+		ParseError { index, kind, pair: None }
+	}
+
+	// Returns a description of the error for the user, e.g. "missing operator between 3 and 5".
+	pub fn message(&self) -> String {
+		match (self.kind, &self.pair) {
+			(ParseErrorKind::Empty, _) => "empty expression".to_string(),
+			(ParseErrorKind::BadStart, _) => "expression cannot start with ')', ',' or a binary operator".to_string(),
+			(ParseErrorKind::BadEnd, _) => "expression cannot end with an operator, a function name, '(' or ','".to_string(),
+			(ParseErrorKind::UnmatchedRightParen, _) => "')' without a matching '('".to_string(),
+			(ParseErrorKind::UnmatchedLeftParen, _) => "'(' is never closed".to_string(),
+			(ParseErrorKind::UnknownOperator, _) => "operator is not in the operator table".to_string(),
+			(ParseErrorKind::UnknownFunction, _) => "function is not in the operator table".to_string(),
+			(ParseErrorKind::Arity { expected, found }, _) => format!("function takes {} argument(s) but was given {}", expected, found),
+			(ParseErrorKind::MisplacedComma, _) => "',' outside the arguments of a function".to_string(),
+			(ParseErrorKind::BadPair, &Some((ref a, ref b))) => match (a, b) {
+				(&InfixToken::LeftParen, &InfixToken::RightParen) => "empty parentheses".to_string(),
+				(&InfixToken::Function(..), _) => format!("'{}' must be followed by '('", a),
+				(&InfixToken::Operator(..), _) | (&InfixToken::LeftParen, _) | (&InfixToken::Comma, _) => format!("missing operand between '{}' and '{}'", a, b),
+				_ => format!("missing operator between '{}' and '{}'", a, b),
+			},
+			(ParseErrorKind::BadPair, None) => "unexpected token".to_string(),
 		}
 	}
+
+	// Renders the tokens on one line and underlines the offending token, or both tokens
+	// of a bad pair, on the next, followed by the message:
+	//
+	//     3 + * 4
+	//       ^^^ missing operand between '+' and '*'
+	pub fn render(&self, tokens: &[InfixToken]) -> String {
+		let mut source = String::new();
+		let mut spans = Vec::new();
+		for t in tokens {
+			if !source.is_empty() {
+				source.push(' ');
+			}
+			let start = source.len();
+			source.push_str(&t.to_string());
+			spans.push(start..source.len());
+		}
+		self.render_source(&source, &spans)
+	}
+
+	// Renders 'source' and underlines the offending tokens as 'render' does, where
+	// 'spans' holds the bytes of each token, e.g. from 'tokenize_spans'.
+	pub fn render_source(&self, source: &str, spans: &[Span]) -> String {
+		let end = source.len();
+		let (start, stop) = match spans.get(self.index) {
+			Some(span) if self.pair.is_some() && self.index > 0 => (spans[self.index - 1].start, span.end),
+			Some(span) => (span.start, span.end),
+			// past the last token, e.g. for an empty expression
+			None => (end, end + 1),
+		};
+		let pad: String = source[..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+		let marks = source.get(start..stop).map_or(1, |s| s.chars().count()).max(1);
+		format!("{}\n{}{} {}", source, pad, "^".repeat(marks), self.message())
+	}
 }
 
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} at token {}", self.message(), self.index)
+	}
+}
+
+impl error::Error for ParseError {}
+
 // Transforms an infix expression to a postfix expression.
 //
-// If the infix expression is valid, outputs 'Some(_)';
-// Otherwise, outputs 'None'
-pub fn infix_to_postfix(tokens: &[InfixToken]) -> Option<Vec<PostfixToken>> {
+// If the infix expression is valid, outputs 'Ok(_)';
+// Otherwise, outputs 'Err(ParseError)' saying which token is wrong and why.
+pub fn infix_to_postfix(tokens: &[InfixToken]) -> Result<Vec<PostfixToken>, ParseError> {
+	infix_to_postfix_with(tokens, &OperatorTable::new())
+}
+
+// Transforms an infix expression to a postfix expression like 'infix_to_postfix', with
+// the operators, precedences and associativities of 'table'.
+//
+// An operator not in 'table' fails with 'ParseErrorKind::UnknownOperator', and a
+// function not in it with 'ParseErrorKind::UnknownFunction'.
+pub fn infix_to_postfix_with(tokens: &[InfixToken], table: &OperatorTable) -> Result<Vec<PostfixToken>, ParseError> {
 	let size = tokens.len();
 	if size == 0 {
-		return None;
+		return Err(ParseError::at(0, ParseErrorKind::Empty));
+	}
+	// an operator is prefix where an operand is expected: a '-' or '+' at the start,
+	// after another operator, '(' or ',', and one of arity 1 anywhere. A prefix '-' is
+	// 'Neg', so it needs 'Neg' in the table.
+	let mut prefix = Vec::with_capacity(size);
+	for (i, t) in tokens.iter().enumerate() {
+		let operand_expected = i == 0 || matches!(tokens[i - 1], InfixToken::Operator(..) | InfixToken::LeftParen | InfixToken::Comma);
+		prefix.push(match *t {
+			InfixToken::Operator(ref op) => match table.get(op) {
+				Some(info) if info.arity == 1 => true,
+				Some(_) => operand_expected && (*op == Operator::Add || (*op == Operator::Sub && table.get(&Operator::Neg).is_some())),
+				None => return Err(ParseError::at(i, ParseErrorKind::UnknownOperator)),
+			},
+			InfixToken::Function(ref name) if table.function(name).is_none() => {
+				return Err(ParseError::at(i, ParseErrorKind::UnknownFunction));
+			}
+			_ => false,
+		});
 	}
 	// rule 2a  
-	// check to see if expression begins with an operator or a RightParen
+	// check to see if expression begins with a binary operator, a comma or a RightParen
 	match tokens[0] {
-		InfixToken::RightParen => return None,
-		InfixToken::Operator(Operator::Add) => return None,
-		InfixToken::Operator(Operator::Sub) => return None,
-		InfixToken::Operator(Operator::Mul) => return None,
-		InfixToken::Operator(Operator::Div) => return None,
+		InfixToken::RightParen | InfixToken::Comma => return Err(ParseError::at(0, ParseErrorKind::BadStart)),
+		InfixToken::Operator(..) if !prefix[0] => return Err(ParseError::at(0, ParseErrorKind::BadStart)),
 		_ => {}
 	}
 	//iterate through the infix input 
 	//tokens is a slice  
 	// i is a reference to the index of tokens, type &InfixToken
+	// an operand, a variable or ')' completes an operand
+	let ends_operand = |t: &InfixToken| matches!(*t, InfixToken::Operand(..) | InfixToken::Var(..) | InfixToken::RightParen);
+	let is_function = |t: &InfixToken| matches!(*t, InfixToken::Function(..));
 	for i in 0..size-1 {
-		// checking rule 2b
-		match (&tokens[i], &tokens[i +1]) {
-			(&InfixToken::RightParen, &InfixToken::Operand(..)) => return None,
-			(&InfixToken::RightParen, &InfixToken::LeftParen) => return None,
-			(&InfixToken::LeftParen, &InfixToken::RightParen) => return None,
-			(&InfixToken::Operator(..), &InfixToken::RightParen) => return None,
-			(&InfixToken::Operator(..), &InfixToken::Operator(..)) => return None,
-			(&InfixToken::Operand(..), &InfixToken::Operand(..)) => return None,
-			(&InfixToken::Operand(..), &InfixToken::LeftParen) => return None,
-			(&InfixToken::LeftParen, &InfixToken::Operator(..)) => return None,
-			_ => {}
+		// checking rule 2b; a prefix operator goes where an operand would
+		let (a, b) = (&tokens[i], &tokens[i + 1]);
+		let bad = match *b {
+			// a function name is always followed by its '('
+			_ if is_function(a) => *b != InfixToken::LeftParen,
+			InfixToken::Operator(..) if ends_operand(a) => prefix[i + 1],
+			InfixToken::Operator(..) => !prefix[i + 1],
+			// empty parentheses only call a function without arguments
+			InfixToken::RightParen if *a == InfixToken::LeftParen => i == 0 || !is_function(&tokens[i - 1]),
+			InfixToken::RightParen | InfixToken::Comma => !ends_operand(a),
+			// an operand, a variable, a function name or '('
+			_ => ends_operand(a),
+		};
+		if bad {
+			return Err(ParseError { index: i + 1, kind: ParseErrorKind::BadPair, pair: Some((a.clone(), b.clone())) });
 		}
 	}
 	//checking for the last token in the expression
-	// cannot end with an operator or a left parenthesis 
+	// cannot end with an operator, a function name, a left parenthesis or a comma
 	match tokens[size-1] {
-		InfixToken::Operator(Operator::Add) => return None,
-		InfixToken::Operator(Operator::Sub) => return None,
-		InfixToken::Operator(Operator::Mul) => return None,
-		InfixToken::Operator(Operator::Div) => return None,
-		InfixToken::LeftParen => return None,
+		InfixToken::Operator(..) | InfixToken::Function(..) | InfixToken::LeftParen | InfixToken::Comma => {
+			return Err(ParseError::at(size - 1, ParseErrorKind::BadEnd));
+		}
 		_ => {}
 	}
+	// every ')' must close a '(' before it, and every '(' must be closed. A ',' must be
+	// directly inside the parentheses of a call, and a call must have as many arguments
+	// as its function takes.
+	// each open '(' with, for a call, the commas seen so far
+	let mut open: Vec<(usize, Option<usize>)> = Vec::new();
+	for (i, t) in tokens.iter().enumerate() {
+		match *t {
+			InfixToken::LeftParen if i > 0 && is_function(&tokens[i - 1]) => open.push((i, Some(0))),
+			InfixToken::LeftParen => open.push((i, None)),
+			InfixToken::Comma => match open.last_mut() {
+				Some(&mut (_, Some(ref mut commas))) => *commas += 1,
+				_ => return Err(ParseError::at(i, ParseErrorKind::MisplacedComma)),
+			},
+			InfixToken::RightParen => match open.pop() {
+				None => return Err(ParseError::at(i, ParseErrorKind::UnmatchedRightParen)),
+				Some((paren, Some(commas))) => {
+					let found = if paren == i - 1 { 0 } else { commas + 1 };
+					if let InfixToken::Function(ref name) = tokens[paren - 1] {
+						let expected = table.function(name).unwrap();
+						if found != expected {
+							return Err(ParseError::at(paren - 1, ParseErrorKind::Arity { expected, found }));
+						}
+					}
+				}
+				Some(_) => {}
+			},
+			_ => {}
+		}
+	}
+	if let Some(&(i, _)) = open.last() {
+		return Err(ParseError::at(i, ParseErrorKind::UnmatchedLeftParen));
+	}
 	
 
 	// Transforming 
@@ -89,135 +301,84 @@ pub fn infix_to_postfix(tokens: &[InfixToken]) -> Option<Vec<PostfixToken>> {
 	// output vector to be outputed 
 	let mut output: Vec<PostfixToken> = Vec::new();
 	// create stack 
-	// initializes vector s for stack
+	// initializes vector s for stack of operators, function names and left parens
 	let mut s: Vec<InfixToken> = Vec::new();
-	let mut index = 0;
 	// scans each token 
-	for t in tokens {
+	for (t, &prefix) in tokens.iter().zip(&prefix) {
 		match *t {
 			// if operand, then push to output 
 			InfixToken::Operand(x) => output.push(PostfixToken::Operand(x)),
-			// if left paren, then push into stack
-			InfixToken::LeftParen => s.push(InfixToken::LeftParen),
+			InfixToken::Var(ref name) => output.push(PostfixToken::Var(name.clone())),
+			// if left paren or function name, then push into stack
+			InfixToken::LeftParen | InfixToken::Function(..) => s.push(t.clone()),
+			// if comma, output operators till the left paren of the call, which stays
+			InfixToken::Comma => {
+				while let Some(InfixToken::Operator(op)) = s.last() {
+					output.push(PostfixToken::Operator(op.clone()));
+					s.pop();
+				}
+			}
 			// if right paren, 1. pop 2. output operators till left paren is left
-			// pop left paren and discard 
+			// pop left paren and discard; if it opened a call, output the call
 			InfixToken::RightParen => {
-				//let mut k = s.len();
-				if s.len() == 0 {
-					return None;
-				}
-				while s[s.len()-1] != InfixToken::LeftParen {
-					let x = s.pop().unwrap();
-					match &x {
-						&InfixToken::Operator(Operator::Add) => output.push(PostfixToken::Operator(Operator::Add)),
-						&InfixToken::Operator(Operator::Sub) => output.push(PostfixToken::Operator(Operator::Sub)),
-						&InfixToken::Operator(Operator::Mul) => output.push(PostfixToken::Operator(Operator::Mul)),
-						&InfixToken::Operator(Operator::Div) => output.push(PostfixToken::Operator(Operator::Div)),
-						_ => {}
-					}
+				while let Some(InfixToken::Operator(op)) = s.last() {
+					output.push(PostfixToken::Operator(op.clone()));
+					s.pop();
 				}
-				// if '(' on stack, discard '(' 
-				if s[s.len()-1] == InfixToken::LeftParen {
+				s.pop();
+				if let Some(InfixToken::Function(name)) = s.last() {
+					output.push(PostfixToken::Function(name.clone(), table.function(name).unwrap()));
 					s.pop();
 				}
 			}
-			// if operator...
+			// a prefix '+' leaves its operand as it is
+			InfixToken::Operator(Operator::Add) if prefix => {}
+			// a prefix operator has no operand on its left yet, so nothing to pop
+			InfixToken::Operator(Operator::Sub) if prefix => s.push(InfixToken::Operator(Operator::Neg)),
+			InfixToken::Operator(..) if prefix => s.push(t.clone()),
+			// if operator, pop & output the operators on top that are applied first,
+			// then push it
 			InfixToken::Operator(ref op) => {
-				let mut Slen = s.len();
-				// if the stack is empty, then push onto stack
-				if Slen == 0 {
-					s.push(InfixToken::Operator(*op));
-				} else if s[Slen-1] != InfixToken::LeftParen {
-					let mut op1 = InfixToken::Operator(*op);
-					let mut op2 = InfixToken::Operator(Operator::Add);
-					loop {
-						// if the stack is empty, go ahead and push the current op1 to the stack
-						if Slen == 0 {
-
-							match &op1 {
-								&InfixToken::Operator(Operator::Add) => s.push(InfixToken::Operator(Operator::Add)),
-								&InfixToken::Operator(Operator::Sub) => s.push(InfixToken::Operator(Operator::Sub)),
-								&InfixToken::Operator(Operator::Mul) => s.push(InfixToken::Operator(Operator::Mul)),
-								&InfixToken::Operator(Operator::Div) => s.push(InfixToken::Operator(Operator::Div)),
-								_ => {}
-							}
-							break;
-						}
-						match &s[Slen -1] {
-							&InfixToken::Operator(Operator::Add) => op2 = InfixToken::Operator(Operator::Add),
-							&InfixToken::Operator(Operator::Sub) => op2 = InfixToken::Operator(Operator::Sub),
-							&InfixToken::Operator(Operator::Mul) => op2 = InfixToken::Operator(Operator::Mul),
-							&InfixToken::Operator(Operator::Div) => op2 = InfixToken::Operator(Operator::Div),
-							_ => {}
-
-						}
-						// if top stack precedence (op2) > = token precedence (op1) 
-						// then pop & output operator from stack (op2)  
-						if op2.precedence() >= op1.precedence() {
-							let p = s.pop().unwrap();
-							match &p {
-								&InfixToken::Operator(Operator::Add) => output.push(PostfixToken::Operator(Operator::Add)),
-								&InfixToken::Operator(Operator::Sub) => output.push(PostfixToken::Operator(Operator::Sub)),
-								&InfixToken::Operator(Operator::Mul) => output.push(PostfixToken::Operator(Operator::Mul)),
-								&InfixToken::Operator(Operator::Div) => output.push(PostfixToken::Operator(Operator::Div)),
-								_ => {}
-							}
-							Slen = s.len();
-						} else {
-							match &op1 {
-								&InfixToken::Operator(Operator::Add) => s.push(InfixToken::Operator(Operator::Add)),
-								&InfixToken::Operator(Operator::Sub) => s.push(InfixToken::Operator(Operator::Sub)),
-								&InfixToken::Operator(Operator::Mul) => s.push(InfixToken::Operator(Operator::Mul)),
-								&InfixToken::Operator(Operator::Div) => s.push(InfixToken::Operator(Operator::Div)),
-								_ => {}
-							}
-							break;
-						}
- 
-					} // end of loop
-
-				 // runs if the stack starts with a left paren
-				} else {
-					match &tokens[index] {
-						&InfixToken::Operator(Operator::Add) => s.push(InfixToken::Operator(Operator::Add)),
-						&InfixToken::Operator(Operator::Sub) => s.push(InfixToken::Operator(Operator::Sub)),
-						&InfixToken::Operator(Operator::Mul) => s.push(InfixToken::Operator(Operator::Mul)),
-						&InfixToken::Operator(Operator::Div) => s.push(InfixToken::Operator(Operator::Div)),
-						_ => {}
+				while let Some(InfixToken::Operator(top)) = s.last() {
+					if !applies_before(table, top, op) {
+						break;
 					}
-				}	
+					output.push(PostfixToken::Operator(top.clone()));
+					s.pop();
+				}
+				s.push(t.clone());
 			}
-
 		} // end of match statement
-	// increment index	
-	index = index + 1; 
 	} // end of scan tokens 
-	let mut sizeFs = s.len();
-
-	// pop and output all the remaining tokens on the stack
-	while sizeFs >= 1 {
-		let remaining = s.pop().unwrap();
-		match &remaining {
-			&InfixToken::Operator(Operator::Add) => output.push(PostfixToken::Operator(Operator::Add)),
-			&InfixToken::Operator(Operator::Sub) => output.push(PostfixToken::Operator(Operator::Sub)),
-			&InfixToken::Operator(Operator::Mul) => output.push(PostfixToken::Operator(Operator::Mul)),
-			&InfixToken::Operator(Operator::Div) => output.push(PostfixToken::Operator(Operator::Div)),
-			&InfixToken::LeftParen => return None,
-			_ => {}
+
+	// pop and output all the remaining operators on the stack
+	while let Some(remaining) = s.pop() {
+		if let InfixToken::Operator(op) = remaining {
+			output.push(PostfixToken::Operator(op));
 		}
-		sizeFs = s.len();
 	}
 
-	return Some(output);
+	Ok(output)
 
 }
 
+// Whether 'top', waiting on the stack, is applied before the binary operator 'op' that
+// follows it: it binds tighter, or as tightly and 'op' groups to the left.
+fn applies_before(table: &OperatorTable, top: &Operator, op: &Operator) -> bool {
+	match (table.get(top), table.get(op)) {
+		(Some(t), Some(o)) => t.precedence > o.precedence || (t.precedence == o.precedence && o.assoc == Assoc::Left),
+		_ => false,
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::Operator;
 	use super::InfixToken;
 	use super::PostfixToken;
 	use super::infix_to_postfix;
+	use super::ParseErrorKind;
+	use super::{infix_to_postfix_with, Assoc, OperatorTable};
     #[test]
     fn test1() {
 
@@ -242,7 +403,7 @@ mod tests {
     	// comparing to see if valid 
     	println!("{:?}", p);
     	println!("{:?}", infix_to_postfix(&tokens));
-    	assert_eq!(Some(p),infix_to_postfix(&tokens));
+    	assert_eq!(Ok(p),infix_to_postfix(&tokens));
     }
     #[test]
     fn test2() {
@@ -268,7 +429,7 @@ mod tests {
     	// comparing to see if valid 
     	println!("{:?}", p);
     	println!("{:?}", infix_to_postfix(&tokens));
-    	assert_eq!(Some(p),infix_to_postfix(&tokens));
+    	assert_eq!(Ok(p),infix_to_postfix(&tokens));
     }
 
     #[test]
@@ -296,7 +457,7 @@ mod tests {
     	// comparing to see if valid 
     	println!("{:?}", p);
     	println!("{:?}", infix_to_postfix(&tokens));
-    	assert_eq!(Some(p),infix_to_postfix(&tokens));
+    	assert_eq!(Ok(p),infix_to_postfix(&tokens));
     }
 
     #[test] 
@@ -326,7 +487,7 @@ mod tests {
     	// comparing to see if valid 
     	println!("{:?}", p);
     	println!("{:?}", infix_to_postfix(&tokens));
-    	assert_eq!(Some(p),infix_to_postfix(&tokens));
+    	assert_eq!(Ok(p),infix_to_postfix(&tokens));
     }
     #[test]
     fn testcase1 () {
@@ -342,7 +503,7 @@ mod tests {
                   InfixToken::Operator(Operator::Mul),
                   InfixToken::Operand(2),
               ];
-        let y = Some(vec![
+        let y = Ok(vec![
                  PostfixToken::Operand(3),
                  PostfixToken::Operand(4),
                  PostfixToken::Operator(Operator::Mul),
@@ -371,7 +532,7 @@ let x = &[InfixToken::Operand(3),
           InfixToken::Operator(Operator::Mul),
           InfixToken::Operand(10),
       ];
-      let y = Some(vec![
+      let y = Ok(vec![
          PostfixToken::Operand(3),
          PostfixToken::Operand(4),
          PostfixToken::Operator(Operator::Sub),
@@ -402,7 +563,7 @@ fn testcase3(){
               InfixToken::Operator(Operator::Mul),
               InfixToken::Operand(4),
           ];
-          let y = Some(vec![
+          let y = Ok(vec![
              PostfixToken::Operand(6),
              PostfixToken::Operand(4),
              PostfixToken::Operator(Operator::Add),
@@ -429,7 +590,7 @@ let x = &[InfixToken::Operand(3),
           InfixToken::Operator(Operator::Add),
           InfixToken::Operand(5),
           InfixToken::RightParen];
-      let y = Some(vec![
+      let y = Ok(vec![
          PostfixToken::Operand(3),
          PostfixToken::Operand(4),
          PostfixToken::Operand(5),
@@ -451,7 +612,7 @@ fn testcase5(){
           InfixToken::Operand(4),
           InfixToken::RightParen,
       ];
-      let y = Some(vec![
+      let y = Ok(vec![
          PostfixToken::Operand(3),
          PostfixToken::Operand(4),
          PostfixToken::Operator(Operator::Add),
@@ -472,7 +633,7 @@ fn testcase6 (){
               InfixToken::Operator(Operator::Sub),
               InfixToken::Operand(6),
           ];
-          let y = Some(vec![
+          let y = Ok(vec![
              PostfixToken::Operand(5),
              PostfixToken::Operand(3),
              PostfixToken::Operator(Operator::Add),
@@ -494,8 +655,8 @@ fn testcase7 (){
               InfixToken::Operator(Operator::Sub),
               InfixToken::Operand(6),
           ];
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((1, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase8 (){
@@ -506,8 +667,8 @@ fn testcase8 (){
           InfixToken::Operand(5),
           InfixToken::Operand(6),
     ];
-    let y = None;
-    assert_eq!(y,infix_to_postfix(x));
+    let y = Err((1, ParseErrorKind::BadPair));
+    assert_eq!(y,infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase9 (){
@@ -521,8 +682,8 @@ fn testcase9 (){
         InfixToken::RightParen,
         InfixToken::RightParen,
     ];
-    let y = None;
-    assert_eq!(y,infix_to_postfix(x));
+    let y = Err((5, ParseErrorKind::UnmatchedRightParen));
+    assert_eq!(y,infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase10 () {
@@ -537,7 +698,7 @@ fn testcase10 () {
     InfixToken::RightParen,
     InfixToken::RightParen,
     ];
-    let y = Some(vec![
+    let y = Ok(vec![
    PostfixToken::Operand(3),
    PostfixToken::Operand(5),
    PostfixToken::Operator(Operator::Add)]);
@@ -552,8 +713,8 @@ fn testcase11 (){
     InfixToken::Operator(Operator::Add),
     InfixToken::Operator(Operator::Add)];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((2, ParseErrorKind::BadEnd));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase12 (){
@@ -567,8 +728,8 @@ fn testcase12 (){
         InfixToken::Operand(5),
         InfixToken::RightParen,
     ];
-    let y = None;
-    assert_eq!(y,infix_to_postfix(x));
+    let y = Err((0, ParseErrorKind::UnmatchedLeftParen));
+    assert_eq!(y,infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase13 (){
@@ -586,8 +747,8 @@ fn testcase13 (){
         InfixToken::Operand(5),
         InfixToken::RightParen,
     ];
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((5, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase14 (){
@@ -601,8 +762,8 @@ fn testcase14 (){
         InfixToken::RightParen,
         InfixToken::Operand(5),
     ];
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((5, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase15 (){
@@ -615,8 +776,8 @@ fn testcase15 (){
         InfixToken::Operand(5),
         InfixToken::LeftParen,
     ];
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((0, ParseErrorKind::BadStart));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase16 (){
@@ -627,24 +788,24 @@ fn testcase16 (){
     InfixToken::Operand(4),
     InfixToken::Operator(Operator::Add)];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((1, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase17 (){
     let x = &[];
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((0, ParseErrorKind::Empty));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase18 (){
     //INFIX: +3
-    //POSTFIX: ERROR
+    //POSTFIX: 3
     let x = &[
     InfixToken::Operator(Operator::Add),
     InfixToken::Operand(3)];
 
-    let y = None;
+    let y = Ok(vec![PostfixToken::Operand(3)]);
     assert_eq!(y, infix_to_postfix(x));
 }
 #[test]
@@ -657,13 +818,13 @@ fn testcase19 (){
     InfixToken::Operator(Operator::Mul),
     InfixToken::Operand(3)];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((2, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase20 (){
     //INFIX: +(3+3)
-    //POSTFIX: ERROR
+    //POSTFIX: 3 3 +
     let x = &[
     InfixToken::Operator(Operator::Add),
     InfixToken::LeftParen,
@@ -672,7 +833,10 @@ fn testcase20 (){
     InfixToken::Operand(3),
     InfixToken::RightParen];
 
-    let y = None;
+    let y = Ok(vec![
+    PostfixToken::Operand(3),
+    PostfixToken::Operand(3),
+    PostfixToken::Operator(Operator::Add)]);
     assert_eq!(y, infix_to_postfix(x));
 }
 #[test]
@@ -687,8 +851,8 @@ fn testcase21 (){
     InfixToken::Operator(Operator::Add),
     InfixToken::RightParen];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((5, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 
 }
 #[test]
@@ -705,8 +869,8 @@ fn testcase22 (){
     InfixToken::Operator(Operator::Add),
     InfixToken::Operand(3)];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((5, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 
 }
 #[test]
@@ -728,8 +892,8 @@ fn testcase23 (){
     InfixToken::RightParen
     ];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((7, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase24 (){
@@ -740,8 +904,8 @@ fn testcase24 (){
     InfixToken::RightParen
     ];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((1, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase25 () {
@@ -754,8 +918,8 @@ fn testcase25 () {
     InfixToken::Operator(Operator::Add),
     InfixToken::Operand(4)];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((0, ParseErrorKind::BadStart));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase26 (){
@@ -767,8 +931,8 @@ fn testcase26 (){
     InfixToken::Operand(4),
     InfixToken::Operator(Operator::Sub),];
 
-    let y = None;
-    assert_eq!(y, infix_to_postfix(x));
+    let y = Err((3, ParseErrorKind::BadEnd));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
 }
 #[test]
 fn testcase27 (){
@@ -781,7 +945,10 @@ fn testcase27 (){
     InfixToken::Operand(4),
     InfixToken::RightParen];
 
-    let y = None;
+    let y = Ok(vec![
+    PostfixToken::Operand(3),
+    PostfixToken::Operand(4),
+    PostfixToken::Operator(Operator::Add)]);
     assert_eq!(y, infix_to_postfix(x));
 }
 #[test]
@@ -797,7 +964,349 @@ fn testcase28 (){
     InfixToken::LeftParen,
     InfixToken::RightParen];
 
-    let y = None;
+    let y = Err((5, ParseErrorKind::BadPair));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
+}
+#[test]
+fn testcase29 (){
+    //INFIX: 8*(3*4-5)
+    //POSTFIX: 8 3 4 * 5 - *
+    let x = &[
+    InfixToken::Operand(8),
+    InfixToken::Operator(Operator::Mul),
+    InfixToken::LeftParen,
+    InfixToken::Operand(3),
+    InfixToken::Operator(Operator::Mul),
+    InfixToken::Operand(4),
+    InfixToken::Operator(Operator::Sub),
+    InfixToken::Operand(5),
+    InfixToken::RightParen];
+
+    let y = Ok(vec![
+    PostfixToken::Operand(8),
+    PostfixToken::Operand(3),
+    PostfixToken::Operand(4),
+    PostfixToken::Operator(Operator::Mul),
+    PostfixToken::Operand(5),
+    PostfixToken::Operator(Operator::Sub),
+    PostfixToken::Operator(Operator::Mul)]);
+    assert_eq!(y, infix_to_postfix(x));
+}
+#[test]
+fn testcase30 (){
+    //INFIX: 3+4)
+    //POSTFIX: ERROR
+    let x = &[
+    InfixToken::Operand(3),
+    InfixToken::Operator(Operator::Add),
+    InfixToken::Operand(4),
+    InfixToken::RightParen];
+
+    let y = Err((3, ParseErrorKind::UnmatchedRightParen));
+    assert_eq!(y, infix_to_postfix(x).map_err(|e| (e.index, e.kind)));
+}
+#[test]
+fn unary_minus_and_plus (){
+    use Operator::{Add, Mul, Neg, Sub};
+    let post = |x: &[InfixToken]| infix_to_postfix(x).map_err(|e| (e.index, e.kind));
+
+    //INFIX: -3*2
+    //POSTFIX: 3 neg 2 *
+    let x = &[
+    InfixToken::Operator(Sub),
+    InfixToken::Operand(3),
+    InfixToken::Operator(Mul),
+    InfixToken::Operand(2)];
+    let y = Ok(vec![
+    PostfixToken::Operand(3),
+    PostfixToken::Operator(Neg),
+    PostfixToken::Operand(2),
+    PostfixToken::Operator(Mul)]);
+    assert_eq!(y, post(x));
+
+    //INFIX: 4*-(1+2)
+    //POSTFIX: 4 1 2 + neg *
+    let x = &[
+    InfixToken::Operand(4),
+    InfixToken::Operator(Mul),
+    InfixToken::Operator(Sub),
+    InfixToken::LeftParen,
+    InfixToken::Operand(1),
+    InfixToken::Operator(Add),
+    InfixToken::Operand(2),
+    InfixToken::RightParen];
+    let y = Ok(vec![
+    PostfixToken::Operand(4),
+    PostfixToken::Operand(1),
+    PostfixToken::Operand(2),
+    PostfixToken::Operator(Add),
+    PostfixToken::Operator(Neg),
+    PostfixToken::Operator(Mul)]);
+    assert_eq!(y, post(x));
+
+    //INFIX: 5- -+3
+    //POSTFIX: 5 3 neg -
+    let x = &[
+    InfixToken::Operand(5),
+    InfixToken::Operator(Sub),
+    InfixToken::Operator(Sub),
+    InfixToken::Operator(Add),
+    InfixToken::Operand(3)];
+    let y = Ok(vec![
+    PostfixToken::Operand(5),
+    PostfixToken::Operand(3),
+    PostfixToken::Operator(Neg),
+    PostfixToken::Operator(Sub)]);
+    assert_eq!(y, post(x));
+
+    //INFIX: 1+-2, with the Neg token p1 prints
+    //POSTFIX: 1 2 neg +
+    let x = &[
+    InfixToken::Operand(1),
+    InfixToken::Operator(Add),
+    InfixToken::Operator(Neg),
+    InfixToken::Operand(2)];
+    let y = Ok(vec![
+    PostfixToken::Operand(1),
+    PostfixToken::Operand(2),
+    PostfixToken::Operator(Neg),
+    PostfixToken::Operator(Add)]);
+    assert_eq!(y, post(x));
+
+    // a Neg token is never binary
+    let x = &[
+    InfixToken::Operand(1),
+    InfixToken::Operator(Neg),
+    InfixToken::Operand(2)];
+    assert_eq!(Err((1, ParseErrorKind::BadPair)), post(x));
+    assert_eq!(Err((1, ParseErrorKind::BadEnd)), post(&[InfixToken::Operator(Sub), InfixToken::Operator(Sub)]));
+}
+#[test]
+fn right_associative_power (){
+    use Operator::{Mul, Pow};
+
+    //INFIX: 2^3^2
+    //POSTFIX: 2 3 2 ^ ^
+    let x = &[
+    InfixToken::Operand(2),
+    InfixToken::Operator(Pow),
+    InfixToken::Operand(3),
+    InfixToken::Operator(Pow),
+    InfixToken::Operand(2)];
+    let y = Ok(vec![
+    PostfixToken::Operand(2),
+    PostfixToken::Operand(3),
+    PostfixToken::Operand(2),
+    PostfixToken::Operator(Pow),
+    PostfixToken::Operator(Pow)]);
     assert_eq!(y, infix_to_postfix(x));
+
+    //INFIX: 2*3^2^-1*4
+    //POSTFIX: 2 3 2 1 neg ^ ^ * 4 *
+    let x = &[
+    InfixToken::Operand(2),
+    InfixToken::Operator(Mul),
+    InfixToken::Operand(3),
+    InfixToken::Operator(Pow),
+    InfixToken::Operand(2),
+    InfixToken::Operator(Pow),
+    InfixToken::Operator(Operator::Sub),
+    InfixToken::Operand(1),
+    InfixToken::Operator(Mul),
+    InfixToken::Operand(4)];
+    let y = Ok(vec![
+    PostfixToken::Operand(2),
+    PostfixToken::Operand(3),
+    PostfixToken::Operand(2),
+    PostfixToken::Operand(1),
+    PostfixToken::Operator(Operator::Neg),
+    PostfixToken::Operator(Pow),
+    PostfixToken::Operator(Pow),
+    PostfixToken::Operator(Mul),
+    PostfixToken::Operand(4),
+    PostfixToken::Operator(Mul)]);
+    assert_eq!(y, infix_to_postfix(x));
+}
+#[test]
+fn custom_operator_table (){
+    use Operator::{Add, Mul, Pow};
+    // the symbols need not be known when the program is compiled
+    let custom = |symbol: &str| Operator::Custom(symbol.to_string());
+    let mut table = OperatorTable::new();
+    table.define(custom("%"), 2, Assoc::Left, 2);
+    table.define(custom("!"), 5, Assoc::Right, 1);
+    table.define(Pow, 4, Assoc::Left, 2);
+
+    //INFIX: 7%3*2^2^!1
+    //POSTFIX: 7 3 % 2 2 ^ 1 ! ^ *
+    let x = &[
+    InfixToken::Operand(7),
+    InfixToken::Operator(custom("%")),
+    InfixToken::Operand(3),
+    InfixToken::Operator(Mul),
+    InfixToken::Operand(2),
+    InfixToken::Operator(Pow),
+    InfixToken::Operand(2),
+    InfixToken::Operator(Pow),
+    InfixToken::Operator(custom("!")),
+    InfixToken::Operand(1)];
+    let y = Ok(vec![
+    PostfixToken::Operand(7),
+    PostfixToken::Operand(3),
+    PostfixToken::Operator(custom("%")),
+    PostfixToken::Operand(2),
+    PostfixToken::Operand(2),
+    PostfixToken::Operator(Pow),
+    PostfixToken::Operand(1),
+    PostfixToken::Operator(custom("!")),
+    PostfixToken::Operator(Pow),
+    PostfixToken::Operator(Mul)]);
+    assert_eq!(y, infix_to_postfix_with(x, &table));
+
+    // a prefix operator cannot follow an operand
+    let x = &[
+    InfixToken::Operand(1),
+    InfixToken::Operator(custom("!")),
+    InfixToken::Operand(2)];
+    assert_eq!(Err((1, ParseErrorKind::BadPair)), infix_to_postfix_with(x, &table).map_err(|e| (e.index, e.kind)));
+
+    let x = &[
+    InfixToken::Operand(1),
+    InfixToken::Operator(Add),
+    InfixToken::Operand(7),
+    InfixToken::Operator(custom("%")),
+    InfixToken::Operand(3)];
+    let e = infix_to_postfix(x).unwrap_err();
+    assert_eq!((3, ParseErrorKind::UnknownOperator), (e.index, e.kind));
+    assert_eq!("1 + 7 % 3\n      ^ operator is not in the operator table", e.render(x));
+}
+#[test]
+fn function_calls (){
+    use Operator::{Add, Mul};
+    let f = |name: &str| InfixToken::Function(name.to_string());
+    let post = |x: &[InfixToken]| infix_to_postfix(x).map_err(|e| (e.index, e.kind));
+
+    //INFIX: max(1,2*abs(-3))+4
+    //POSTFIX: 1 2 -3 abs * max 4 +
+    let x = &[
+    f("max"),
+    InfixToken::LeftParen,
+    InfixToken::Operand(1),
+    InfixToken::Comma,
+    InfixToken::Operand(2),
+    InfixToken::Operator(Mul),
+    f("abs"),
+    InfixToken::LeftParen,
+    InfixToken::Operand(-3),
+    InfixToken::RightParen,
+    InfixToken::RightParen,
+    InfixToken::Operator(Add),
+    InfixToken::Operand(4)];
+    let y = Ok(vec![
+    PostfixToken::Operand(1),
+    PostfixToken::Operand(2),
+    PostfixToken::Operand(-3),
+    PostfixToken::Function("abs".to_string(), 1),
+    PostfixToken::Operator(Mul),
+    PostfixToken::Function("max".to_string(), 2),
+    PostfixToken::Operand(4),
+    PostfixToken::Operator(Add)]);
+    assert_eq!(y, post(x));
+
+    //INFIX: max((1),2)
+    //POSTFIX: 1 2 max
+    let x = &[
+    f("max"),
+    InfixToken::LeftParen,
+    InfixToken::LeftParen,
+    InfixToken::Operand(1),
+    InfixToken::RightParen,
+    InfixToken::Comma,
+    InfixToken::Operand(2),
+    InfixToken::RightParen];
+    let y = Ok(vec![
+    PostfixToken::Operand(1),
+    PostfixToken::Operand(2),
+    PostfixToken::Function("max".to_string(), 2)]);
+    assert_eq!(y, post(x));
+
+    // a function without arguments, from a custom table
+    let mut table = OperatorTable::new();
+    table.define_function("pi", 0);
+    let x = &[f("pi"), InfixToken::LeftParen, InfixToken::RightParen, InfixToken::Operator(Mul), InfixToken::Operand(2)];
+    let y = Ok(vec![PostfixToken::Function("pi".to_string(), 0), PostfixToken::Operand(2), PostfixToken::Operator(Mul)]);
+    assert_eq!(y, infix_to_postfix_with(x, &table));
+
+    //INFIX: max(1)
+    let x = &[f("max"), InfixToken::LeftParen, InfixToken::Operand(1), InfixToken::RightParen];
+    assert_eq!(Err((0, ParseErrorKind::Arity { expected: 2, found: 1 })), post(x));
+    //INFIX: abs()
+    let x = &[f("abs"), InfixToken::LeftParen, InfixToken::RightParen];
+    assert_eq!(Err((0, ParseErrorKind::Arity { expected: 1, found: 0 })), post(x));
+    //INFIX: gcd(4,6)
+    let x = &[f("gcd"), InfixToken::LeftParen, InfixToken::Operand(4), InfixToken::Comma, InfixToken::Operand(6), InfixToken::RightParen];
+    assert_eq!(Err((0, ParseErrorKind::UnknownFunction)), post(x));
+    //INFIX: 1,2
+    let x = &[InfixToken::Operand(1), InfixToken::Comma, InfixToken::Operand(2)];
+    assert_eq!(Err((1, ParseErrorKind::MisplacedComma)), post(x));
+    //INFIX: max((1,2))
+    let x = &[f("max"), InfixToken::LeftParen, InfixToken::LeftParen, InfixToken::Operand(1), InfixToken::Comma, InfixToken::Operand(2),
+    InfixToken::RightParen, InfixToken::RightParen];
+    assert_eq!(Err((4, ParseErrorKind::MisplacedComma)), post(x));
+    //INFIX: max(1,,2)
+    let x = &[f("max"), InfixToken::LeftParen, InfixToken::Operand(1), InfixToken::Comma, InfixToken::Comma, InfixToken::Operand(2),
+    InfixToken::RightParen];
+    assert_eq!(Err((4, ParseErrorKind::BadPair)), post(x));
+    //INFIX: max(1,)
+    let x = &[f("max"), InfixToken::LeftParen, InfixToken::Operand(1), InfixToken::Comma, InfixToken::RightParen];
+    assert_eq!(Err((4, ParseErrorKind::BadPair)), post(x));
+    //INFIX: ,1
+    assert_eq!(Err((0, ParseErrorKind::BadStart)), post(&[InfixToken::Comma, InfixToken::Operand(1)]));
+    //INFIX: abs 1
+    let x = &[f("abs"), InfixToken::Operand(1)];
+    let e = infix_to_postfix(x).unwrap_err();
+    assert_eq!("abs 1\n^^^^^ 'abs' must be followed by '('", e.render(x));
+    //INFIX: 2abs(1)
+    let x = &[InfixToken::Operand(2), f("abs"), InfixToken::LeftParen, InfixToken::Operand(1), InfixToken::RightParen];
+    assert_eq!(Err((1, ParseErrorKind::BadPair)), post(x));
+    //INFIX: 1+abs
+    assert_eq!(Err((2, ParseErrorKind::BadEnd)), post(&[InfixToken::Operand(1), InfixToken::Operator(Add), f("abs")]));
+}
+#[test]
+fn error_pairs_and_messages (){
+    //INFIX: 3+*4
+    let x = &[
+    InfixToken::Operand(3),
+    InfixToken::Operator(Operator::Add),
+    InfixToken::Operator(Operator::Mul),
+    InfixToken::Operand(4)];
+    let e = infix_to_postfix(x).unwrap_err();
+    assert_eq!(Some((InfixToken::Operator(Operator::Add), InfixToken::Operator(Operator::Mul))), e.pair);
+    assert_eq!("missing operand between '+' and '*' at token 2", e.to_string());
+    assert_eq!("3 + * 4\n  ^^^ missing operand between '+' and '*'", e.render(x));
+
+    //INFIX: (3+4)5
+    let x = &[
+    InfixToken::LeftParen,
+    InfixToken::Operand(3),
+    InfixToken::Operator(Operator::Add),
+    InfixToken::Operand(4),
+    InfixToken::RightParen,
+    InfixToken::Operand(-5)];
+    let e = infix_to_postfix(x).unwrap_err();
+    assert_eq!("( 3 + 4 ) -5\n        ^^^^ missing operator between ')' and '-5'", e.render(x));
+
+    //INFIX: ((3+4)
+    let x = &[
+    InfixToken::LeftParen,
+    InfixToken::LeftParen,
+    InfixToken::Operand(3),
+    InfixToken::Operator(Operator::Add),
+    InfixToken::Operand(4),
+    InfixToken::RightParen];
+    let e = infix_to_postfix(x).unwrap_err();
+    assert_eq!(None, e.pair);
+    assert_eq!("( ( 3 + 4 )\n^ '(' is never closed", e.render(x));
+    assert_eq!("\n^ empty expression", infix_to_postfix(&[]).unwrap_err().render(&[]));
 }
 }
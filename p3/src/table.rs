@@ -0,0 +1,125 @@
+// The operators and functions 'infix_to_postfix_with' knows, with how tightly each
+// operator binds and how many arguments each function takes. 'tokenize_with' reads the
+// symbols of its binary operators.
+use Operator;
+
+// Which way a chain of operators with the same precedence groups.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Assoc {
+	// 'a - b - c' is '(a - b) - c'
+	Left,
+	// 'a ^ b ^ c' is 'a ^ (b ^ c)'
+	Right,
+}
+
+// How an operator is parsed. Its symbol is the one 'Display' prints.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OperatorInfo {
+	// higher binds tighter
+	pub precedence: i32,
+	pub assoc: Assoc,
+	// 1 for a prefix operator such as 'Neg', 2 for a binary one
+	pub arity: usize,
+}
+
+// A set of operators and functions for the shunting-yard conversion, so new ones can
+// be added with 'define' and 'define_function' rather than by changing
+// 'infix_to_postfix_with'.
+#[derive(Clone, Debug)]
+pub struct OperatorTable {
+	ops: Vec<(Operator, OperatorInfo)>,
+	// each function's name and number of arguments
+	functions: Vec<(String, usize)>,
+}
+
+impl OperatorTable {
+	// The built-in operators, loosest first: '+ -', '* /', unary minus and a
+	// right-associative '^'. Unary minus is looser than '^', so '-2 ^ 2' is '-(2 ^ 2)'.
+	// The built-in functions are 'abs' of one argument, and 'min' and 'max' of two.
+	pub fn new() -> OperatorTable {
+		let mut table = OperatorTable { ops: Vec::new(), functions: Vec::new() };
+		table.define(Operator::Add, 1, Assoc::Left, 2);
+		table.define(Operator::Sub, 1, Assoc::Left, 2);
+		table.define(Operator::Mul, 2, Assoc::Left, 2);
+		table.define(Operator::Div, 2, Assoc::Left, 2);
+		table.define(Operator::Neg, 3, Assoc::Right, 1);
+		table.define(Operator::Pow, 4, Assoc::Right, 2);
+		table.define_function("abs", 1);
+		table.define_function("min", 2);
+		table.define_function("max", 2);
+		table
+	}
+
+	// Adds 'op', replacing what the table said about it before.
+	pub fn define(&mut self, op: Operator, precedence: i32, assoc: Assoc, arity: usize) {
+		let info = OperatorInfo { precedence, assoc, arity };
+		match self.ops.iter_mut().find(|&&mut (ref o, _)| *o == op) {
+			Some(entry) => entry.1 = info,
+			None => self.ops.push((op, info)),
+		}
+	}
+
+	// Returns how 'op' is parsed, or 'None' if it is not in the table.
+	pub fn get(&self, op: &Operator) -> Option<OperatorInfo> {
+		self.ops.iter().find(|&(o, _)| o == op).map(|&(_, info)| info)
+	}
+
+	// Adds the function 'name' of 'arity' arguments, replacing what the table said about
+	// it before.
+	pub fn define_function(&mut self, name: &str, arity: usize) {
+		match self.functions.iter_mut().find(|&&mut (ref f, _)| f == name) {
+			Some(entry) => entry.1 = arity,
+			None => self.functions.push((name.to_string(), arity)),
+		}
+	}
+
+	// Returns how many arguments the function 'name' takes, or 'None' if it is not in the
+	// table.
+	pub fn function(&self, name: &str) -> Option<usize> {
+		self.functions.iter().find(|&(f, _)| f == name).map(|&(_, arity)| arity)
+	}
+
+	// Returns the binary operator whose symbol is the longest one 'text' starts with, if
+	// there is one. 'tokenize_with' reads operators with it.
+	pub fn find(&self, text: &str) -> Option<Operator> {
+		self.ops.iter()
+			.filter(|&(op, info)| info.arity == 2 && text.starts_with(op.symbol()))
+			.max_by_key(|&(op, _)| op.symbol().len())
+			.map(|(op, _)| op.clone())
+	}
+}
+
+impl Default for OperatorTable {
+	fn default() -> OperatorTable {
+		OperatorTable::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn defines_operators() {
+		let mut table = OperatorTable::new();
+		assert_eq!(Some(OperatorInfo { precedence: 4, assoc: Assoc::Right, arity: 2 }), table.get(&Operator::Pow));
+		assert_eq!(None, table.get(&Operator::Custom("%".to_string())));
+		table.define(Operator::Custom("%".to_string()), 2, Assoc::Left, 2);
+		table.define(Operator::Pow, 5, Assoc::Right, 2);
+		assert_eq!(Some(5), table.get(&Operator::Pow).map(|i| i.precedence));
+		assert_eq!(Some(Operator::Custom("%".to_string())), table.find("%"));
+		// '-' is 'Sub' as a binary operator
+		assert_eq!(Some(Operator::Sub), table.find("-"));
+		table.define(Operator::Custom("**".to_string()), 4, Assoc::Right, 2);
+		assert_eq!(Some(Operator::Mul), table.find("*2"));
+		assert_eq!(Some(Operator::Custom("**".to_string())), table.find("**2"));
+		assert_eq!(None, table.find("2"));
+
+		assert_eq!(Some(2), table.function("max"));
+		assert_eq!(None, table.function("gcd"));
+		table.define_function("gcd", 2);
+		table.define_function("max", 3);
+		assert_eq!(Some(2), table.function("gcd"));
+		assert_eq!(Some(3), table.function("max"));
+	}
+}
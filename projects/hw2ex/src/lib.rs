@@ -2,13 +2,30 @@ use std::{ops, fmt};
 
 // PartialEq -> comparision trait
 // Debug -> to format a value using the {:?} formatter 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 // generic struct 
 pub struct Complex<T> {
 	re: T,
 	im: T,
 }
 
+impl<T: Copy> Complex<T> {
+	/// Creates the complex number 're + im j'.
+	pub fn new(re: T, im: T) -> Complex<T> {
+		Complex { re, im }
+	}
+
+	/// Returns the real part.
+	pub fn re(&self) -> T {
+		self.re
+	}
+
+	/// Returns the imaginary part.
+	pub fn im(&self) -> T {
+		self.im
+	}
+}
+
 // + operator overloaded with the Add trait 
 impl < T:ops::Add<Output=T> > ops::Add for Complex<T> {
 	type Output = Self;